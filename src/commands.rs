@@ -1,5 +1,7 @@
 automod::dir!("src/commands");
 
+use std::slice;
+
 use anyhow::Result;
 use clap::Subcommand;
 
@@ -11,6 +13,7 @@ pub(crate) enum Command {
     ExtCommand(ExtCommand),
 
     Convert(convert::Args),
+    Generate(generate::Args),
     Version(version::Args),
 }
 
@@ -23,6 +26,7 @@ pub(crate) enum ExtCommand {
     Start(start::Args),
     Stop(stop::Args),
     Restart(restart::Args),
+    Scale(scale::Args),
     Kill(kill::Args),
     Pause(pause::Args),
     Unpause(unpause::Args),
@@ -34,14 +38,27 @@ pub(crate) enum ExtCommand {
     Ps(ps::Args),
     Top(top::Args),
     Port(port::Args),
+    Wait(wait::Args),
+    Watch(watch::Args),
     Ls(ls::Args),
+    Build(build::Args),
+    Pull(pull::Args),
+    Push(push::Args),
 }
 
-pub(crate) async fn run(command: Command, config: Config) -> Result<()> {
+/// Run a command and return the code the process should exit with
+pub(crate) async fn run(command: Command, config: Config) -> Result<i32> {
     match command {
         Command::ExtCommand(command) => {
             let podman = Podman::new(&config).await?;
-            let file = compose::parse(&config, false)?;
+            let services = match &command {
+                ExtCommand::Up(args) => args.services.as_slice(),
+                ExtCommand::Create(args) => args.services.as_slice(),
+                ExtCommand::Start(args) => args.services.as_slice(),
+                ExtCommand::Run(args) => slice::from_ref(&args.service),
+                _ => &[],
+            };
+            let file = compose::parse(&config, false, services)?;
 
             match command {
                 ExtCommand::Up(args) => up::run(args, &podman, &file, &config).await,
@@ -51,6 +68,7 @@ pub(crate) async fn run(command: Command, config: Config) -> Result<()> {
                 ExtCommand::Start(args) => start::run(args, &podman, &file, &config).await,
                 ExtCommand::Stop(args) => stop::run(args, &podman, &file, &config).await,
                 ExtCommand::Restart(args) => restart::run(args, &podman, &file, &config).await,
+                ExtCommand::Scale(args) => scale::run(args, &podman, &file, &config).await,
                 ExtCommand::Kill(args) => kill::run(args, &podman, &file, &config).await,
                 ExtCommand::Pause(args) => pause::run(args, &podman, &file, &config).await,
                 ExtCommand::Unpause(args) => unpause::run(args, &podman, &file, &config).await,
@@ -62,12 +80,18 @@ pub(crate) async fn run(command: Command, config: Config) -> Result<()> {
                 ExtCommand::Ps(args) => ps::run(args, &podman, &file).await,
                 ExtCommand::Top(args) => top::run(args, &podman, &file).await,
                 ExtCommand::Port(args) => port::run(args, &podman, &file).await,
+                ExtCommand::Wait(args) => return wait::run(args, &podman, &file).await,
+                ExtCommand::Watch(args) => watch::run(args, &podman, &file, &config).await,
                 ExtCommand::Ls(args) => ls::run(args, &podman).await,
+                ExtCommand::Build(args) => build::run(args, &podman, &file, &config).await,
+                ExtCommand::Pull(args) => pull::run(args, &podman, &file, &config).await,
+                ExtCommand::Push(args) => push::run(args, &podman, &file, &config).await,
             }?;
         }
         Command::Convert(args) => convert::run(args, &config)?,
+        Command::Generate(args) => generate::run(args, &config)?,
         Command::Version(args) => version::run(args),
     }
 
-    Ok(())
+    Ok(0)
 }
@@ -0,0 +1,250 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+use clap::crate_version;
+use futures::{stream::FuturesUnordered, TryStreamExt};
+
+use crate::{
+    compose::types::Compose,
+    config::Config,
+    podman::Podman,
+    progress::{Finish, Progress},
+    utils::parse_key_val_opt,
+};
+
+/// Build or rebuild services
+#[derive(clap::Args, Debug)]
+#[command(next_display_order = None)]
+pub(crate) struct Args {
+    pub(crate) services: Vec<String>,
+
+    /// Set build-time variables for services
+    #[arg(long, value_parser = parse_key_val_opt::<String, String>)]
+    pub(crate) build_arg: Vec<(String, Option<String>)>,
+
+    /// Do not use cache when building the image
+    #[arg(long)]
+    pub(crate) no_cache: bool,
+
+    /// Always attempt to pull a newer version of the image
+    #[arg(long)]
+    pub(crate) pull: bool,
+}
+
+async fn build_images(
+    podman: &Podman,
+    progress: &Progress,
+    file: &Compose,
+    config: &Config,
+    services: &[&String],
+    args: Args,
+) -> Result<()> {
+    let project_name = file.name.as_ref().unwrap();
+    let args = &args;
+
+    services
+        .iter()
+        .map(|service_name| async move {
+            let service = &file.services[*service_name];
+            let build = service.build.as_ref().unwrap();
+            let image = file.image_name(service_name);
+            let spinner = progress.add_spinner(format!("Image {image}"), "Building");
+
+            let image_labels = [
+                ("version", crate_version!()),
+                ("project", project_name),
+                ("service", service_name),
+            ]
+            .into_iter()
+            .map(|label| format!("io.podman.compose.{}={}", label.0, label.1))
+            .collect::<Vec<_>>();
+            let build_args = build
+                .args
+                .iter()
+                .filter(|(key, _)| args.build_arg.iter().all(|(arg, _)| arg != *key))
+                .chain(args.build_arg.iter().map(|(key, value)| (key, value)))
+                .map(|(key, value)| {
+                    if let Some(value) = value {
+                        format!("{key}={value}")
+                    } else {
+                        key.clone()
+                    }
+                })
+                .collect::<Vec<_>>();
+            let secrets = build
+                .secrets
+                .iter()
+                .map(|secret| {
+                    let id = secret.target.as_ref().unwrap_or(&secret.source);
+                    let source = &file.secrets[&secret.source];
+
+                    if let Some(environment) = &source.environment {
+                        Ok(format!("id={id},env={environment}"))
+                    } else if let Some(path) = &source.file {
+                        Ok(format!("id={id},src={}", path.display()))
+                    } else {
+                        bail!(
+                            "External secret \"{}\" cannot be used to build an image",
+                            secret.source
+                        );
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            // Removed when dropped, even if another build fails while this one is running
+            let inline_dockerfile = if let Some(dockerfile_inline) = &build.dockerfile_inline {
+                let mut inline_dockerfile = tempfile::Builder::new()
+                    .prefix(&format!("haddock_{project_name}_{service_name}_"))
+                    .tempfile()?;
+
+                if !config.dry_run {
+                    inline_dockerfile.write_all(dockerfile_inline.as_bytes())?;
+                }
+
+                Some(inline_dockerfile)
+            } else {
+                None
+            };
+            let dockerfile = inline_dockerfile
+                .as_ref()
+                .map(|dockerfile| dockerfile.path().to_string_lossy().to_string());
+
+            let result = podman
+                .run(
+                    ["build", "--tag", &image]
+                        .into_iter()
+                        .chain(image_labels.iter().flat_map(|label| ["--label", label]))
+                        .chain(if args.no_cache {
+                            vec!["--no-cache"]
+                        } else {
+                            vec![]
+                        })
+                        .chain(if args.pull {
+                            vec!["--pull=always"]
+                        } else {
+                            vec![]
+                        })
+                        .chain(
+                            build_args
+                                .iter()
+                                .flat_map(|build_arg| ["--build-arg", build_arg]),
+                        )
+                        .chain(secrets.iter().flat_map(|secret| ["--secret", secret]))
+                        .chain(if let Some(dockerfile) = &dockerfile {
+                            vec!["--file", dockerfile]
+                        } else {
+                            vec![]
+                        })
+                        .chain(build.to_args().iter().map(AsRef::as_ref))
+                        .chain([build.context.to_string_lossy().as_ref()]),
+                )
+                .await;
+
+            result.finish_with_message(spinner, "Built")
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect::<Vec<_>>()
+        .await
+        .map(|_| ())
+}
+
+pub(crate) async fn run(
+    args: Args,
+    podman: &Podman,
+    file: &Compose,
+    config: &Config,
+) -> Result<()> {
+    for service in &args.services {
+        if !file.services.contains_key(service) {
+            bail!("No such service: \"{service}\"");
+        }
+    }
+
+    let services = file
+        .services
+        .iter()
+        .filter_map(|(name, service)| {
+            if service.build.is_some() && (args.services.is_empty() || args.services.contains(name))
+            {
+                Some(name)
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if !services.is_empty() {
+        let progress = Progress::new(config);
+
+        build_images(podman, &progress, file, config, &services, args).await?;
+
+        progress.finish();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::podman::fake::{self, Operation};
+
+    #[tokio::test]
+    async fn build_arg_override() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/build/compose.yaml");
+
+        run(
+            fake::args(&["--build-arg", "GREETING=bye", "full"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap();
+
+        let targets = engine.targets(Operation::Run);
+        assert_eq!(targets.len(), 1);
+
+        let build_args = shell_words::split(&targets[0])
+            .unwrap()
+            .windows(2)
+            .filter(|pair| pair[0] == "--build-arg")
+            .map(|pair| pair[1].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(build_args, ["EMPTY", "GREETING=bye"]);
+    }
+
+    #[tokio::test]
+    async fn dockerfile_inline() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/build/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&["inline"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        let args = shell_words::split(&engine.targets(Operation::Run)[0]).unwrap();
+        let dockerfile = Path::new(&args[args.iter().position(|arg| arg == "--file").unwrap() + 1]);
+        assert!(dockerfile
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with(&format!("haddock_{name}_inline_")));
+        assert!(!dockerfile.exists());
+    }
+
+    #[tokio::test]
+    async fn unknown_service() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/build/compose.yaml");
+
+        let err = run(fake::args(&["missing"]), &podman, &file, &config)
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "No such service: \"missing\"");
+        assert!(engine.state().calls.is_empty());
+    }
+}
@@ -54,7 +54,7 @@ enum Format {
 }
 
 pub(crate) fn run(args: Args, config: &Config) -> Result<()> {
-    let file = compose::parse(config, args.no_interpolate)?;
+    let file = compose::parse(config, args.no_interpolate, &[])?;
 
     if !args.quiet {
         if args.services {
@@ -1,11 +1,7 @@
 use anyhow::{anyhow, bail, Result};
 use itertools::Itertools;
 
-use crate::{
-    compose::types::Compose,
-    podman::{types::Container, Podman},
-    utils::parse_container_path,
-};
+use crate::{compose::types::Compose, podman::Podman, utils::parse_container_path};
 
 /// Copy files/folders between a service container and the local filesystem
 #[derive(clap::Args, Debug)]
@@ -26,6 +22,24 @@ pub(crate) struct Args {
     archive: bool,
 }
 
+pub(crate) async fn copy(
+    podman: &Podman,
+    source: &str,
+    destination: &str,
+    archive: bool,
+) -> Result<()> {
+    podman
+        .run(
+            ["cp"]
+                .into_iter()
+                .chain(if archive { vec!["--archive"] } else { vec![] })
+                .chain([source, destination]),
+        )
+        .await?;
+
+    Ok(())
+}
+
 pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<()> {
     match (&args.source.0, &args.destination.0) {
         (Some(_), Some(_)) => bail!("Copying between services is not supported"),
@@ -33,17 +47,9 @@ pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<(
         _ => {}
     }
 
-    let output = podman
-        .force_run([
-            "ps",
-            "--all",
-            "--format",
-            "json",
-            "--filter",
-            &format!("pod={}", file.name.as_ref().unwrap()),
-        ])
-        .await?;
-    let containers = serde_json::from_str::<Vec<Container>>(&output)?
+    let containers = podman
+        .containers([&format!("pod={}", file.name.as_ref().unwrap())])
+        .await?
         .into_iter()
         .filter_map(|mut container| {
             container.labels.and_then(|labels| {
@@ -52,14 +58,12 @@ pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<(
                         .source
                         .0
                         .as_ref()
-                        .map(|source| *source == service)
-                        .unwrap_or_default()
+                        .is_some_and(|source| *source == service)
                         || args
                             .destination
                             .0
                             .as_ref()
-                            .map(|destination| *destination == service)
-                            .unwrap_or_default()
+                            .is_some_and(|destination| *destination == service)
                     {
                         container
                             .names
@@ -94,35 +98,23 @@ pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<(
             .transpose()
     });
 
-    podman
-        .run(
-            ["cp"]
-                .into_iter()
-                .chain(if args.archive {
-                    vec!["--archive"]
-                } else {
-                    vec![]
-                })
-                .chain([
-                    format!(
-                        "{}{}",
-                        source?
-                            .map(|container| format!("{container}:"))
-                            .unwrap_or_default(),
-                        args.source.1
-                    )
-                    .as_ref(),
-                    format!(
-                        "{}{}",
-                        destination?
-                            .map(|container| format!("{container}:"))
-                            .unwrap_or_default(),
-                        args.destination.1
-                    )
-                    .as_ref(),
-                ]),
-        )
-        .await?;
-
-    Ok(())
+    copy(
+        podman,
+        &format!(
+            "{}{}",
+            source?
+                .map(|container| format!("{container}:"))
+                .unwrap_or_default(),
+            args.source.1
+        ),
+        &format!(
+            "{}{}",
+            destination?
+                .map(|container| format!("{container}:"))
+                .unwrap_or_default(),
+            args.destination.1
+        ),
+        args.archive,
+    )
+    .await
 }
@@ -1,14 +1,8 @@
-use std::{
-    collections::VecDeque,
-    env,
-    fmt::{self, Display, Formatter},
-    fs,
-};
+use std::{collections::HashMap, env, fs};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use clap::{crate_version, ValueEnum};
 use futures::{stream::FuturesUnordered, try_join, StreamExt, TryStreamExt};
-use heck::AsKebabCase;
 use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
 use petgraph::{algo::has_path_connecting, graphmap::DiGraphMap, Direction};
@@ -16,12 +10,16 @@ use tokio::sync::{broadcast, Barrier};
 use tokio_stream::wrappers::BroadcastStream;
 
 use crate::{
-    commands::down,
-    compose::types::{Compose, FileReference, ServiceVolume, ServiceVolumeType},
+    commands::{
+        build, pull,
+        rm::{self, remove_containers},
+        stop::{self, stop_containers},
+    },
+    compose::types::{self, Compose, FileReference, Service, ServiceVolume, ServiceVolumeType},
     config::Config,
-    podman::{types::Pod, Podman},
+    podman::{types::Secret, ContainerSpec, PodSpec, Podman, ResourceKind, ResourceSpec},
     progress::{Finish, Progress},
-    utils::Digest,
+    utils::{parse_key_val, Digest},
 };
 
 /// Creates containers for a service
@@ -34,6 +32,14 @@ pub(crate) struct Args {
     #[arg(long, value_enum)]
     pub(crate) pull: Option<PullPolicy>,
 
+    /// Build images before starting containers
+    #[arg(long, conflicts_with = "no_build")]
+    pub(crate) build: bool,
+
+    /// Don't build an image, even if it's missing
+    #[arg(long)]
+    pub(crate) no_build: bool,
+
     /// Recreate containers even if their configuration and image haven't changed
     #[arg(long, conflicts_with_all = ["services", "no_recreate"])]
     pub(crate) force_recreate: bool,
@@ -42,9 +48,25 @@ pub(crate) struct Args {
     #[arg(long)]
     pub(crate) no_recreate: bool,
 
+    /// Don't start linked services
+    #[arg(long)]
+    pub(crate) no_deps: bool,
+
+    /// Recreate dependent containers
+    #[arg(long, conflicts_with = "no_recreate")]
+    pub(crate) always_recreate_deps: bool,
+
+    /// Remove anonymous volumes of recreated containers instead of leaving them behind
+    #[arg(short = 'V', long)]
+    pub(crate) renew_anon_volumes: bool,
+
     /// Remove containers for services not defined in the Compose file
     #[arg(long)]
     pub(crate) remove_orphans: bool,
+
+    /// Scale SERVICE to NUM instances, overrides the `scale` setting in the Compose file if present
+    #[arg(long, value_name = "SERVICE=NUM", value_parser = parse_key_val::<String, u32>)]
+    pub(crate) scale: Vec<(String, u32)>,
 }
 
 #[derive(ValueEnum, Clone, Debug)]
@@ -55,9 +77,14 @@ pub(crate) enum PullPolicy {
     Newer,
 }
 
-impl Display for PullPolicy {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", AsKebabCase(format!("{self:?}")))
+impl From<PullPolicy> for types::PullPolicy {
+    fn from(pull_policy: PullPolicy) -> Self {
+        match pull_policy {
+            PullPolicy::Always => Self::Always,
+            PullPolicy::Missing => Self::Missing,
+            PullPolicy::Never => Self::Never,
+            PullPolicy::Newer => Self::Newer,
+        }
     }
 }
 
@@ -69,7 +96,7 @@ async fn create_pod(
 ) -> Result<()> {
     let name = file.name.as_ref().unwrap();
 
-    if podman.force_run(["pod", "exists", name]).await.is_err() {
+    if !podman.exists("pod", name).await? {
         let pod_labels = [
             (
                 "project.working-dir",
@@ -87,20 +114,16 @@ async fn create_pod(
                 "project.environment-file",
                 config.env_file.to_string_lossy().as_ref(),
             ),
-            ("config-hash", &file.digest()),
         ]
         .into_iter()
         .map(|label| format!("io.podman.compose.{}={}", label.0, label.1))
         .collect::<Vec<_>>();
 
         podman
-            .run(
-                ["pod", "create", "--share", "none"]
-                    .into_iter()
-                    .chain(labels.iter().flat_map(|label| ["--label", label]))
-                    .chain(pod_labels.iter().flat_map(|label| ["--label", label]))
-                    .chain([name.as_ref()]),
-            )
+            .create_pod(&PodSpec {
+                name: name.clone(),
+                labels: labels.iter().cloned().chain(pod_labels).collect(),
+            })
             .await?;
     }
 
@@ -119,7 +142,9 @@ async fn create_networks(
             let name = network.name.as_ref().unwrap();
             let spinner = progress.add_spinner(format!("Network {name}"), "Creating");
 
-            if podman.force_run(["network", "exists", name]).await.is_err() {
+            if podman.exists("network", name).await? {
+                spinner.finish_with_message("Exists");
+            } else {
                 if network.external.unwrap_or_default() {
                     bail!("External network \"{name}\" not found");
                 }
@@ -130,17 +155,15 @@ async fn create_networks(
                     .collect::<Vec<_>>();
 
                 podman
-                    .run(
-                        ["network", "create"]
-                            .into_iter()
-                            .chain(labels.iter().flat_map(|label| ["--label", label]))
-                            .chain(network_labels.iter().flat_map(|label| ["--label", label]))
-                            .chain(network.to_args().iter().map(AsRef::as_ref)),
-                    )
+                    .create_resource(&ResourceSpec {
+                        kind: ResourceKind::Network,
+                        name: name.clone(),
+                        labels: labels.iter().cloned().chain(network_labels).collect(),
+                        options: network.to_args(),
+                        source: None,
+                    })
                     .await
                     .finish_with_message(spinner, "Created")?;
-            } else {
-                spinner.finish_with_message("Exists");
             }
 
             Ok(())
@@ -163,7 +186,9 @@ async fn create_volumes(
             let name = volume.name.as_ref().unwrap();
             let spinner = progress.add_spinner(format!("Volume {name}"), "Creating");
 
-            if podman.force_run(["volume", "exists", name]).await.is_err() {
+            if podman.exists("volume", name).await? {
+                spinner.finish_with_message("Exists");
+            } else {
                 if volume.external.unwrap_or_default() {
                     bail!("External volume \"{name}\" not found");
                 }
@@ -174,17 +199,15 @@ async fn create_volumes(
                     .collect::<Vec<_>>();
 
                 podman
-                    .run(
-                        ["volume", "create"]
-                            .into_iter()
-                            .chain(labels.iter().flat_map(|label| ["--label", label]))
-                            .chain(volume_labels.iter().flat_map(|label| ["--label", label]))
-                            .chain(volume.to_args().iter().map(AsRef::as_ref)),
-                    )
+                    .create_resource(&ResourceSpec {
+                        kind: ResourceKind::Volume,
+                        name: name.clone(),
+                        labels: labels.iter().cloned().chain(volume_labels).collect(),
+                        options: volume.to_args(),
+                        source: None,
+                    })
                     .await
                     .finish_with_message(spinner, "Created")?;
-            } else {
-                spinner.finish_with_message("Exists");
             }
 
             Ok(())
@@ -206,31 +229,148 @@ async fn create_secrets(
         .map(|secret| async {
             let name = secret.name.as_ref().unwrap();
             let spinner = progress.add_spinner(format!("Secret {name}"), "Creating");
+            let existing = existing(podman, name).await?;
 
-            if podman.force_run(["secret", "inspect", name]).await.is_err() {
-                if secret.external.unwrap_or_default() {
+            if secret.external.unwrap_or_default() {
+                if existing.is_none() {
                     bail!("External secret \"{name}\" not found");
                 }
 
-                let secret_labels = [("secret", name)]
-                    .into_iter()
-                    .map(|label| format!("io.podman.compose.{}={}", label.0, label.1))
-                    .collect::<Vec<_>>();
+                spinner.finish_with_message("Exists");
 
-                podman
-                    .run(
-                        ["secret", "create"]
-                            .into_iter()
-                            .chain(labels.iter().flat_map(|label| ["--label", label]))
-                            .chain(secret_labels.iter().flat_map(|label| ["--label", label]))
-                            .chain(secret.to_args().iter().map(AsRef::as_ref)),
-                    )
-                    .await
-                    .finish_with_message(spinner, "Created")?;
+                return Ok(());
+            }
+
+            let content_hash = if let Some(environment) = &secret.environment {
+                env::var(environment).unwrap_or_default().into_bytes()
+            } else if let Some(path) = &secret.file {
+                fs::read(path).with_context(|| format!("{} not found", path.display()))?
             } else {
+                Vec::new()
+            }
+            .digest();
+            let replace = if let Some(existing) = existing {
+                if !is_outdated(existing, &content_hash) {
+                    spinner.finish_with_message("Exists");
+
+                    return Ok(());
+                }
+
+                true
+            } else {
+                false
+            };
+
+            let secret_labels = [("secret", name), ("content-hash", &content_hash)]
+                .into_iter()
+                .map(|label| format!("io.podman.compose.{}={}", label.0, label.1))
+                .collect::<Vec<_>>();
+
+            create_secret(
+                podman,
+                &ResourceSpec {
+                    kind: ResourceKind::Secret,
+                    name: name.clone(),
+                    labels: labels.iter().cloned().chain(secret_labels).collect(),
+                    options: secret.to_args(),
+                    source: secret.source(),
+                },
+                replace,
+            )
+            .await
+            .finish_with_message(spinner, if replace { "Recreated" } else { "Created" })?;
+
+            Ok(())
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect::<Vec<_>>()
+        .await
+        .map(|_| ())
+}
+
+async fn create_configs(
+    podman: &Podman,
+    progress: &Progress,
+    file: &Compose,
+    config: &Config,
+    labels: &[String],
+) -> Result<()> {
+    file.configs
+        .values()
+        .map(|resource| async {
+            let name = resource.name.as_ref().unwrap();
+            let spinner = progress.add_spinner(format!("Config {name}"), "Creating");
+            let existing = existing(podman, name).await?;
+
+            if resource.external.unwrap_or_default() {
+                if existing.is_none() {
+                    bail!("External config \"{name}\" not found");
+                }
+
                 spinner.finish_with_message("Exists");
+
+                return Ok(());
+            }
+
+            let content_hash = if let Some(content) = &resource.content {
+                content.clone().into_bytes()
+            } else if let Some(environment) = &resource.environment {
+                env::var(environment).unwrap_or_default().into_bytes()
+            } else if let Some(path) = &resource.file {
+                fs::read(path).with_context(|| format!("{} not found", path.display()))?
+            } else {
+                Vec::new()
+            }
+            .digest();
+            let replace = if let Some(existing) = existing {
+                if !is_outdated(existing, &content_hash) {
+                    spinner.finish_with_message("Exists");
+
+                    return Ok(());
+                }
+
+                true
+            } else {
+                false
+            };
+
+            let config_labels = [("config", name), ("content-hash", &content_hash)]
+                .into_iter()
+                .map(|label| format!("io.podman.compose.{}={}", label.0, label.1))
+                .collect::<Vec<_>>();
+            let content = if let Some(content) = &resource.content {
+                let path = env::temp_dir().join(format!("haddock_{name}"));
+
+                if !config.dry_run {
+                    fs::write(&path, content)?;
+                }
+
+                Some(path.to_string_lossy().to_string())
+            } else {
+                None
+            };
+
+            let result = create_secret(
+                podman,
+                &ResourceSpec {
+                    kind: ResourceKind::Secret,
+                    name: name.clone(),
+                    labels: labels.iter().cloned().chain(config_labels).collect(),
+                    options: resource.to_args(),
+                    source: content.clone().or_else(|| resource.source()),
+                },
+                replace,
+            )
+            .await;
+
+            if let Some(content) = &content {
+                if !config.dry_run {
+                    fs::remove_file(content)?;
+                }
             }
 
+            result.finish_with_message(spinner, if replace { "Recreated" } else { "Created" })?;
+
             Ok(())
         })
         .collect::<FuturesUnordered<_>>()
@@ -239,15 +379,136 @@ async fn create_secrets(
         .map(|_| ())
 }
 
+async fn existing(podman: &Podman, name: &str) -> Result<Option<Secret>> {
+    Ok(podman
+        .secrets()
+        .await?
+        .into_iter()
+        .find(|secret| secret.spec.name == name))
+}
+
+/// Secrets created without a content hash cannot be compared, so they are always outdated
+fn is_outdated(existing: Secret, content_hash: &str) -> bool {
+    existing
+        .spec
+        .labels
+        .and_then(|labels| labels.content_hash)
+        .is_none_or(|existing_hash| existing_hash != content_hash)
+}
+
+/// Create a secret, removing the existing one first as `podman secret create --replace` needs
+/// Podman 4.7
+async fn create_secret(podman: &Podman, secret: &ResourceSpec, replace: bool) -> Result<()> {
+    if replace {
+        podman
+            .remove_resource(ResourceKind::Secret, &secret.name)
+            .await?;
+    }
+
+    podman.create_resource(secret).await
+}
+
+fn requested_services<'a>(file: &'a Compose, args: &Args) -> Vec<&'a String> {
+    let mut dependencies = file
+        .services
+        .iter()
+        .flat_map(|(to, service)| service.depends_on.keys().map(move |from| (from, to, ())))
+        .collect::<DiGraphMap<_, _>>();
+
+    for service in file.services.keys() {
+        dependencies.add_node(service);
+    }
+
+    file.services
+        .keys()
+        .filter(|name| {
+            args.services.is_empty()
+                || args.services.contains(name)
+                || (!args.no_deps
+                    && args
+                        .services
+                        .iter()
+                        .any(|service| has_path_connecting(&dependencies, name, service, None)))
+        })
+        .collect()
+}
+
+async fn build_images(podman: &Podman, file: &Compose, config: &Config, args: &Args) -> Result<()> {
+    let services = requested_services(file, args)
+        .into_iter()
+        .filter(|name| file.services[*name].build.is_some())
+        .map(|name| async move {
+            let service = &file.services[name];
+
+            anyhow::Ok(
+                (args.build
+                    || service.pull_policy == Some(types::PullPolicy::Build)
+                    || !podman.exists("image", &file.image_name(name)).await?)
+                    .then(|| name.clone()),
+            )
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_filter_map(|service| async move { Ok(service) })
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    if !services.is_empty() {
+        build::run(
+            build::Args {
+                services,
+                build_arg: Vec::new(),
+                no_cache: false,
+                pull: false,
+            },
+            podman,
+            file,
+            config,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn pull_images(
+    podman: &Podman,
+    progress: &Progress,
+    file: &Compose,
+    args: &Args,
+) -> Result<()> {
+    let mut images = IndexMap::new();
+
+    for name in requested_services(file, args) {
+        let service = &file.services[name];
+
+        if service.build.is_none() {
+            images.entry(file.image_name(name)).or_insert_with(|| {
+                args.pull
+                    .clone()
+                    .map(types::PullPolicy::from)
+                    .or_else(|| service.pull_policy.clone())
+                    .unwrap_or(types::PullPolicy::Missing)
+            });
+        }
+    }
+
+    pull::pull_images(podman, progress, &images, false, false).await
+}
+
 async fn create_containers(
     podman: &Podman,
     config: &Config,
     progress: &Progress,
     file: &Compose,
-    labels: &[String],
+    labels: &HashMap<&String, Vec<String>>,
+    containers: &HashMap<String, (String, Option<String>)>,
     args: Args,
 ) -> Result<()> {
     let project_name = file.name.as_ref().unwrap();
+    let recreated = containers
+        .values()
+        .filter_map(|(service, state)| state.is_none().then_some(service))
+        .collect::<IndexSet<_>>();
     let mut dependencies = file
         .services
         .iter()
@@ -262,9 +523,15 @@ async fn create_containers(
         for node in dependencies
             .nodes()
             .filter(|node| {
-                args.services
-                    .iter()
-                    .all(|service| !has_path_connecting(&dependencies, node, service, None))
+                // Dependents removed along with a recreated dependency are always recreated
+                !recreated.contains(node)
+                    && if args.no_deps {
+                        !args.services.contains(node)
+                    } else {
+                        args.services
+                            .iter()
+                            .all(|service| !has_path_connecting(&dependencies, node, service, None))
+                    }
             })
             .collect::<Vec<_>>()
         {
@@ -291,14 +558,7 @@ async fn create_containers(
             .iter()
             .filter_map(|(name, service)| {
                 if dependencies.contains_node(name) {
-                    Some(
-                        service
-                            .deploy
-                            .as_ref()
-                            .and_then(|deploy| deploy.replicas)
-                            .or(service.scale)
-                            .unwrap_or(1) as usize,
-                    )
+                    Some(service.replicas() as usize)
                 } else {
                     None
                 }
@@ -307,24 +567,15 @@ async fn create_containers(
     );
 
     let dependencies = &dependencies;
-    let args = &args;
 
     file.services
         .iter()
         .filter_map(|(service_name, service)| {
             if dependencies.contains_node(service_name) {
                 Some(async move {
-                    let container_names = (1..=service
-                        .deploy
-                        .as_ref()
-                        .and_then(|deploy| deploy.replicas)
-                        .or(service.scale)
-                        .unwrap_or(1))
-                        .map(|i| async move {
-                            let container_name = service
-                                .container_name
-                                .clone()
-                                .unwrap_or_else(|| format!("{project_name}_{service_name}_{i}"));
+                    let container_names = service
+                        .container_names(project_name, service_name)
+                        .map(|(i, container_name)| async move {
                             let spinner = progress
                                 .add_spinner(format!("Container {container_name}"), "Creating");
                             let rx = txs[service_name].subscribe();
@@ -339,11 +590,13 @@ async fn create_containers(
                                 .try_concat()
                                 .await?;
 
-                            if podman
-                                .force_run(["container", "exists", &container_name])
-                                .await
-                                .is_err()
-                            {
+                            if let Some((_, Some(state))) = containers.get(&container_name) {
+                                spinner.finish_with_message(if state == "running" {
+                                    "Running"
+                                } else {
+                                    "Exists"
+                                });
+                            } else {
                                 if !config.dry_run {
                                     for volume in service.volumes.iter().filter(|volume| {
                                         volume
@@ -366,10 +619,6 @@ async fn create_containers(
                                 .into_iter()
                                 .map(|label| format!("io.podman.compose.{}={}", label.0, label.1))
                                 .collect::<Vec<_>>();
-                                let pull_policy =
-                                    args.pull.as_ref().map(ToString::to_string).or_else(|| {
-                                        service.pull_policy.as_ref().map(ToString::to_string)
-                                    });
 
                                 let networks = service
                                     .networks
@@ -432,59 +681,69 @@ async fn create_containers(
                                         .to_string()
                                     })
                                     .collect::<Vec<_>>();
+                                let configs = service
+                                    .configs
+                                    .iter()
+                                    .map(|config| file.config_mount(config))
+                                    .collect::<Vec<_>>();
 
-                                let (global_args, service_args) = service.to_args();
+                                let (global_args, service_args) = Service {
+                                    image: None,
+                                    command: Vec::new(),
+                                    ..service.clone()
+                                }
+                                .to_args();
 
                                 podman
-                                    .run(
-                                        global_args
+                                    .create_container(&ContainerSpec {
+                                        name: container_name.clone(),
+                                        pod: project_name.clone(),
+                                        image: file.image_name(service_name),
+                                        command: service.command.clone(),
+                                        requires: requirements,
+                                        labels: labels[service_name]
                                             .iter()
-                                            .map(AsRef::as_ref)
-                                            .chain([
-                                                "create",
-                                                "--pod",
-                                                project_name,
-                                                "--name",
-                                                &container_name,
-                                            ])
-                                            .chain(requirements.iter().flat_map(|requirement| {
-                                                ["--requires", requirement]
-                                            }))
-                                            .chain(
-                                                labels.iter().flat_map(|label| ["--label", label]),
-                                            )
-                                            .chain(
-                                                container_labels
-                                                    .iter()
-                                                    .flat_map(|label| ["--label", label]),
-                                            )
-                                            .chain(if let Some(pull_policy) = &pull_policy {
-                                                vec!["--pull", pull_policy]
-                                            } else {
-                                                vec![]
-                                            })
-                                            .chain(
-                                                networks
-                                                    .iter()
-                                                    .flat_map(|network| ["--network", network]),
-                                            )
-                                            .chain(if service.networks.contains_key("default") {
-                                                vec!["--network-alias", service_name]
-                                            } else {
-                                                vec![]
+                                            .cloned()
+                                            .chain(container_labels)
+                                            .collect(),
+                                        global_args,
+                                        options: networks
+                                            .into_iter()
+                                            .flat_map(|network| {
+                                                [String::from("--network"), network]
                                             })
-                                            .chain(volumes.iter().map(AsRef::as_ref))
                                             .chain(
-                                                secrets
-                                                    .iter()
-                                                    .flat_map(|secret| ["--secret", secret]),
+                                                service
+                                                    .networks
+                                                    .contains_key("default")
+                                                    .then(|| {
+                                                        [
+                                                            String::from("--network-alias"),
+                                                            service_name.clone(),
+                                                        ]
+                                                    })
+                                                    .into_iter()
+                                                    .flatten(),
                                             )
-                                            .chain(service_args.iter().map(AsRef::as_ref)),
-                                    )
+                                            .chain(volumes)
+                                            .chain(secrets.into_iter().flat_map(|secret| {
+                                                [String::from("--secret"), secret]
+                                            }))
+                                            .chain(configs.into_iter().flat_map(|config| {
+                                                [String::from("--secret"), config]
+                                            }))
+                                            .chain(service_args)
+                                            .collect(),
+                                    })
                                     .await
-                                    .finish_with_message(spinner, "Created")?;
-                            } else {
-                                spinner.finish_with_message("Exists");
+                                    .finish_with_message(
+                                        spinner,
+                                        if containers.contains_key(&container_name) {
+                                            "Recreated"
+                                        } else {
+                                            "Created"
+                                        },
+                                    )?;
                             }
 
                             anyhow::Ok(container_name)
@@ -515,44 +774,18 @@ pub(crate) async fn run(
     file: &Compose,
     config: &Config,
 ) -> Result<()> {
+    let mut file = file.clone();
+    file.scale(&args.scale)?;
+    let file = &file;
+
     let name = file.name.as_ref().unwrap();
     let labels = [("version", crate_version!()), ("project", name)]
         .into_iter()
         .map(|label| format!("io.podman.compose.{}={}", label.0, label.1))
         .collect::<Vec<_>>();
 
-    let output = podman
-        .force_run([
-            "pod",
-            "ps",
-            "--format",
-            "json",
-            "--filter",
-            &format!("name=^{name}$"),
-        ])
-        .await?;
-    let config_hash = serde_json::from_str::<VecDeque<Pod>>(&output)?
-        .pop_front()
-        .and_then(|pod| pod.labels.and_then(|labels| labels.config_hash));
-
-    if args.force_recreate
-        || (!args.no_recreate
-            && config_hash
-                .map(|config_hash| config_hash != file.digest())
-                .unwrap_or_default())
-    {
-        down::run(
-            down::Args {
-                remove_orphans: args.remove_orphans,
-                timeout: None,
-                volumes: true,
-                rmi: false,
-            },
-            podman,
-            file,
-            config,
-        )
-        .await?;
+    if !args.no_build {
+        build_images(podman, file, config, &args).await?;
     }
 
     let progress = Progress::new(config);
@@ -562,10 +795,149 @@ pub(crate) async fn run(
         create_networks(podman, &progress, file, &labels),
         create_volumes(podman, &progress, file, &labels),
         create_secrets(podman, &progress, file, &labels),
+        create_configs(podman, &progress, file, config, &labels),
+        pull_images(podman, &progress, file, &args),
     )?;
 
     progress.finish();
 
+    let config_hashes = file
+        .services
+        .keys()
+        .map(|service| async move {
+            let image_id = podman.image_id(&file.image_name(service)).await?;
+
+            anyhow::Ok((service, file.config_hash(service, image_id.as_deref())))
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect::<HashMap<_, _>>()
+        .await?;
+
+    let containers = podman
+        .containers([
+            "label=io.podman.compose.oneoff=false",
+            &format!("pod={name}"),
+        ])
+        .await?
+        .into_iter()
+        .filter_map(|mut container| {
+            container.labels.and_then(|labels| {
+                labels.service.and_then(|service| {
+                    container.names.pop_front().map(|name| {
+                        (
+                            name,
+                            (
+                                service,
+                                labels.container_number,
+                                labels.config_hash,
+                                container.state,
+                            ),
+                        )
+                    })
+                })
+            })
+        })
+        .collect::<IndexMap<_, _>>();
+
+    let mut dependencies = file
+        .services
+        .iter()
+        .flat_map(|(to, service)| service.depends_on.keys().map(move |from| (from, to, ())))
+        .collect::<DiGraphMap<_, _>>();
+
+    for service in file.services.keys() {
+        dependencies.add_node(service);
+    }
+
+    let services = requested_services(file, &args);
+    let outdated = containers
+        .values()
+        .filter_map(|(service, _, config_hash, _)| {
+            if services.contains(&service)
+                && (args.force_recreate
+                    || (args.always_recreate_deps
+                        && !args.services.contains(service)
+                        && args.services.iter().any(|requested| {
+                            has_path_connecting(&dependencies, service, requested, None)
+                        }))
+                    || (!args.no_recreate && config_hash.as_ref() != Some(&config_hashes[service])))
+            {
+                Some(service)
+            } else {
+                None
+            }
+        })
+        .collect::<IndexSet<_>>();
+    let recreated = containers
+        .iter()
+        .filter_map(|(name, (service, container_number, _, _))| {
+            if let Some(definition) = file.services.get(service) {
+                if outdated.iter().any(|outdated| {
+                    has_path_connecting(&dependencies, *outdated, service, None)
+                }) || (services.contains(&service)
+                    // Scaling down removes the highest-numbered replicas
+                    && container_number.is_some_and(|number| number > definition.replicas() as usize))
+                {
+                    Some((service.clone(), name.clone()))
+                } else {
+                    None
+                }
+            } else if args.remove_orphans {
+                Some((service.clone(), name.clone()))
+            } else {
+                None
+            }
+        })
+        .into_group_map();
+
+    if !recreated.is_empty() {
+        let progress = Progress::new(config);
+
+        stop_containers(
+            podman,
+            &progress,
+            file,
+            &recreated,
+            stop::Args {
+                services: Vec::new(),
+                timeout: None,
+            },
+        )
+        .await?;
+
+        progress.finish();
+
+        let progress = Progress::new(config);
+
+        remove_containers(
+            podman,
+            &progress,
+            file,
+            &recreated,
+            rm::Args {
+                services: Vec::new(),
+                force: true,
+                stop: false,
+                volumes: args.renew_anon_volumes,
+            },
+        )
+        .await?;
+
+        progress.finish();
+    }
+
+    let recreated = recreated.into_values().flatten().collect::<IndexSet<_>>();
+    let containers = containers
+        .into_iter()
+        .map(|(name, (service, _, _, state))| {
+            if recreated.contains(&name) {
+                (name, (service, None))
+            } else {
+                (name, (service, Some(state)))
+            }
+        })
+        .collect::<HashMap<_, _>>();
+
     if args.services.is_empty()
         || !args
             .services
@@ -575,10 +947,411 @@ pub(crate) async fn run(
     {
         let progress = Progress::new(config);
 
-        create_containers(podman, config, &progress, file, &labels, args).await?;
+        let labels = config_hashes
+            .iter()
+            .map(|(service, config_hash)| {
+                (
+                    *service,
+                    labels
+                        .iter()
+                        .cloned()
+                        .chain([format!("io.podman.compose.config-hash={config_hash}")])
+                        .collect(),
+                )
+            })
+            .collect();
+
+        create_containers(podman, config, &progress, file, &labels, &containers, args).await?;
 
         progress.finish();
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::podman::{
+        self,
+        fake::{self, Operation},
+    };
+
+    #[tokio::test]
+    async fn dependencies() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        let state = engine.state();
+        assert!(state.pods.contains_key(name));
+        assert_eq!(
+            state
+                .containers
+                .iter()
+                .map(|(name, container)| (name.as_str(), container.state.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (format!("{name}_dependency_1").as_str(), "created"),
+                (format!("{name}_app_1").as_str(), "created")
+            ]
+        );
+        assert_eq!(
+            state.containers[&format!("{name}_app_1")].labels["io.podman.compose.service"],
+            "app"
+        );
+    }
+
+    #[tokio::test]
+    async fn recreate() {
+        let (config, mut file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.clone().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        engine.clear_calls();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        assert!(engine.targets(Operation::Create).is_empty());
+
+        file.services["dependency"].command = vec![String::from("true")];
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::Remove { volumes: false }),
+            [format!("{name}_app_1"), format!("{name}_dependency_1")]
+        );
+        assert_eq!(engine.targets(Operation::Create).len(), 2);
+
+        engine.clear_calls();
+        run(fake::args(&["--no-recreate"]), &podman, &file, &config)
+            .await
+            .unwrap();
+        assert!(engine
+            .targets(Operation::Remove { volumes: false })
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn scale_down() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/scale/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        engine.clear_calls();
+
+        run(
+            fake::args(&["--scale", "worker=1"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap();
+
+        let mut removed = engine.targets(Operation::Remove { volumes: false });
+        removed.sort();
+        assert_eq!(
+            removed,
+            [format!("{name}_worker_2"), format!("{name}_worker_3")]
+        );
+        assert!(engine.targets(Operation::Create).is_empty());
+        assert!(engine
+            .state()
+            .containers
+            .contains_key(&format!("{name}_worker_1")));
+    }
+
+    #[tokio::test]
+    async fn scale_up() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/scale/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        engine.clear_calls();
+
+        run(fake::args(&["--scale", "web=4"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert!(engine
+            .targets(Operation::Remove { volumes: false })
+            .is_empty());
+        let mut created = engine.targets(Operation::Create);
+        created.sort();
+        assert_eq!(created, [format!("{name}_web_3"), format!("{name}_web_4")]);
+    }
+
+    #[tokio::test]
+    async fn recreate_dependents() {
+        let (config, mut file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.clone().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        engine.clear_calls();
+
+        file.services["dependency"].command = vec![String::from("true")];
+        run(fake::args(&["dependency"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::Create),
+            [format!("{name}_dependency_1"), format!("{name}_app_1")]
+        );
+        assert_eq!(engine.state().containers.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn no_deps() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&["--no-deps", "app"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(engine.targets(Operation::Create), [format!("{name}_app_1")]);
+    }
+
+    #[tokio::test]
+    async fn always_recreate_deps() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        engine.clear_calls();
+
+        // Requested services are only recreated when they changed
+        run(
+            fake::args(&["--always-recreate-deps", "dependency", "app"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap();
+        assert!(engine.targets(Operation::Create).is_empty());
+
+        run(
+            fake::args(&["--always-recreate-deps", "--no-deps", "app"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap();
+        assert!(engine.targets(Operation::Create).is_empty());
+
+        run(
+            fake::args(&["--always-recreate-deps", "app"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            engine.targets(Operation::Remove { volumes: false }),
+            [format!("{name}_app_1"), format!("{name}_dependency_1")]
+        );
+        assert_eq!(
+            engine.targets(Operation::Create),
+            [format!("{name}_dependency_1"), format!("{name}_app_1")]
+        );
+    }
+
+    #[tokio::test]
+    async fn renew_anon_volumes() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        engine.clear_calls();
+
+        run(
+            fake::args(&["--force-recreate", "-V"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::Remove { volumes: true }),
+            [format!("{name}_app_1"), format!("{name}_dependency_1")]
+        );
+        assert!(engine
+            .targets(Operation::Remove { volumes: false })
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn recreate_image() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.clone().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        engine.clear_calls();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        assert!(engine.targets(Operation::Create).is_empty());
+
+        // Both services run the same image
+        engine
+            .state_mut()
+            .images
+            .insert(file.image_name("app"), String::from("sha256:rebuilt"));
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::Create),
+            [format!("{name}_dependency_1"), format!("{name}_app_1")]
+        );
+    }
+
+    #[tokio::test]
+    async fn remove_orphans() {
+        let (config, mut file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.clone().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        file.services.shift_remove("app");
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        assert!(engine
+            .state()
+            .containers
+            .contains_key(&format!("{name}_app_1")));
+
+        run(fake::args(&["--remove-orphans"]), &podman, &file, &config)
+            .await
+            .unwrap();
+        assert_eq!(
+            engine.state().containers.keys().collect::<Vec<_>>(),
+            [&format!("{name}_dependency_1")]
+        );
+    }
+
+    #[tokio::test]
+    async fn resources() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/network-test/compose.yaml");
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        let state = engine.state();
+        for network in file.networks.values() {
+            assert!(state.networks.contains_key(network.name.as_ref().unwrap()));
+        }
+        assert_eq!(state.containers.len(), file.services.len());
+    }
+
+    #[tokio::test]
+    async fn refresh_secrets() {
+        let (config, mut file, engine, podman) =
+            fake::setup("tests/fixtures/env-secret/compose.yaml");
+        let secret = file.secrets["bar"].name.clone().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        assert_eq!(
+            engine.targets(Operation::CreateResource(ResourceKind::Secret)),
+            [secret.as_str()]
+        );
+
+        engine.clear_calls();
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        assert!(engine
+            .targets(Operation::CreateResource(ResourceKind::Secret))
+            .is_empty());
+
+        file.secrets["bar"].environment = None;
+        file.secrets["bar"].file = Some(PathBuf::from("tests/fixtures/configs/app.conf"));
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        assert_eq!(
+            engine.targets(Operation::RemoveResource(ResourceKind::Secret)),
+            [secret.as_str()]
+        );
+        assert_eq!(
+            engine.targets(Operation::CreateResource(ResourceKind::Secret)),
+            [secret.as_str()]
+        );
+        assert!(engine.state().secrets[&secret].contains_key("io.podman.compose.content-hash"));
+    }
+
+    #[tokio::test]
+    async fn refresh_secrets_without_hash() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/env-secret/compose.yaml");
+        let secret = file.secrets["bar"].name.clone().unwrap();
+        engine
+            .state_mut()
+            .secrets
+            .insert(secret.clone(), IndexMap::new());
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::RemoveResource(ResourceKind::Secret)),
+            [secret.as_str()]
+        );
+        assert_eq!(
+            engine.targets(Operation::CreateResource(ResourceKind::Secret)),
+            [secret]
+        );
+    }
+
+    #[tokio::test]
+    async fn pull_policies() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/compose-pull/policies/docker-compose.yaml");
+        engine
+            .state_mut()
+            .images
+            .insert(String::from("alpine:3.19"), String::from("sha256:19"));
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        let mut pulled = engine.targets(Operation::Pull(podman::PullPolicy::Always));
+        pulled.sort();
+        assert_eq!(pulled, ["alpine:3.16", "alpine:3.18", "alpine:3.21"]);
+        assert_eq!(
+            engine.targets(Operation::Pull(podman::PullPolicy::Newer)),
+            ["alpine:3.17"]
+        );
+        assert_eq!(engine.state().containers.len(), file.services.len());
+
+        engine.clear_calls();
+        run(fake::args(&["--pull", "never"]), &podman, &file, &config)
+            .await
+            .unwrap();
+        assert!(engine
+            .state()
+            .calls
+            .iter()
+            .all(|call| !matches!(call.operation, Operation::Pull(_))));
+
+        engine.clear_calls();
+        run(fake::args(&["--pull", "newer"]), &podman, &file, &config)
+            .await
+            .unwrap();
+        assert_eq!(
+            engine
+                .targets(Operation::Pull(podman::PullPolicy::Newer))
+                .len(),
+            file.services.len()
+        );
+    }
+}
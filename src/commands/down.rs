@@ -1,6 +1,8 @@
 use anyhow::Result;
-use futures::{future::try_join3, stream::FuturesUnordered, try_join, TryStreamExt};
-use itertools::Itertools;
+use clap::ValueEnum;
+use futures::{future::try_join4, stream::FuturesUnordered, try_join, TryStreamExt};
+use indexmap::IndexSet;
+use itertools::{Either, Itertools};
 
 use crate::{
     commands::{
@@ -9,11 +11,9 @@ use crate::{
     },
     compose::types::Compose,
     config::Config,
-    podman::{
-        types::{Container, Network, Volume},
-        Podman,
-    },
+    podman::{Podman, ResourceKind},
     progress::{Finish, Progress},
+    utils::STYLED_WARNING,
 };
 
 /// Stop and remove containers, networks
@@ -32,9 +32,15 @@ pub(crate) struct Args {
     #[arg(short, long)]
     pub(crate) volumes: bool,
 
-    /// Remove images used by services
-    #[arg(long)]
-    pub(crate) rmi: bool,
+    /// Remove images used by services, "local" remove only images that don't have a custom tag
+    #[arg(long, value_enum)]
+    pub(crate) rmi: Option<ImageType>,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ImageType {
+    Local,
+    All,
 }
 
 async fn remove_networks(podman: &Podman, progress: &Progress, networks: &[String]) -> Result<()> {
@@ -44,7 +50,7 @@ async fn remove_networks(podman: &Podman, progress: &Progress, networks: &[Strin
             let spinner = progress.add_spinner(format!("Network {network}"), "Removing");
 
             podman
-                .run(["network", "rm", network])
+                .remove_resource(ResourceKind::Network, network)
                 .await
                 .finish_with_message(spinner, "Removed")
         })
@@ -61,7 +67,75 @@ async fn remove_volumes(podman: &Podman, progress: &Progress, volumes: &[String]
             let spinner = progress.add_spinner(format!("Volume {volume}"), "Removing");
 
             podman
-                .run(["volume", "rm", volume])
+                .remove_resource(ResourceKind::Volume, volume)
+                .await
+                .finish_with_message(spinner, "Removed")
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect::<Vec<_>>()
+        .await
+        .map(|_| ())
+}
+
+async fn remove_images<'a>(
+    podman: &Podman,
+    progress: &Progress,
+    images: &'a IndexSet<String>,
+) -> Result<Vec<&'a String>> {
+    let skipped = images
+        .iter()
+        .map(|image| async move {
+            if !podman.exists("image", image).await? {
+                return Ok(None);
+            }
+
+            let spinner = progress.add_spinner(format!("Image {image}"), "Removing");
+            let containers = podman.containers([format!("ancestor={image}")]).await?;
+
+            if containers.is_empty() {
+                podman
+                    .remove_image(image)
+                    .await
+                    .finish_with_message(spinner, "Removed")
+                    .map(|()| None)
+            } else {
+                spinner.finish_with_message("Skipped");
+
+                Ok(Some(image))
+            }
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    Ok(skipped.into_iter().flatten().collect())
+}
+
+async fn remove_secrets(podman: &Podman, progress: &Progress, secrets: &[String]) -> Result<()> {
+    secrets
+        .iter()
+        .map(|secret| async move {
+            let spinner = progress.add_spinner(format!("Secret {secret}"), "Removing");
+
+            podman
+                .remove_resource(ResourceKind::Secret, secret)
+                .await
+                .finish_with_message(spinner, "Removed")
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect::<Vec<_>>()
+        .await
+        .map(|_| ())
+}
+
+async fn remove_configs(podman: &Podman, progress: &Progress, configs: &[String]) -> Result<()> {
+    configs
+        .iter()
+        .map(|config| async move {
+            let spinner = progress.add_spinner(format!("Config {config}"), "Removing");
+
+            podman
+                .remove_resource(ResourceKind::Secret, config)
                 .await
                 .finish_with_message(spinner, "Removed")
         })
@@ -79,43 +153,23 @@ pub(crate) async fn run(
 ) -> Result<()> {
     let name = file.name.as_ref().unwrap();
 
-    let (containers, networks, volumes) = try_join3(
-        podman.force_run(
-            [
-                "ps",
-                "--all",
-                "--format",
-                "json",
-                "--filter",
-                &format!("pod={name}"),
-            ]
-            .into_iter()
-            .chain(if args.remove_orphans {
-                vec![]
-            } else {
-                vec!["--filter", "label=io.podman.compose.oneoff=false"]
-            }),
+    let (containers, networks, volumes, secrets) = try_join4(
+        podman.containers(
+            [format!("pod={name}")]
+                .into_iter()
+                .chain(if args.remove_orphans {
+                    None
+                } else {
+                    Some(String::from("label=io.podman.compose.oneoff=false"))
+                }),
         ),
-        podman.force_run([
-            "network",
-            "ls",
-            "--format",
-            "json",
-            "--filter",
-            &format!("label=io.podman.compose.project={name}"),
-        ]),
-        podman.force_run([
-            "volume",
-            "ls",
-            "--format",
-            "json",
-            "--filter",
-            &format!("label=io.podman.compose.project={name}"),
-        ]),
+        podman.networks([format!("label=io.podman.compose.project={name}")]),
+        podman.volumes([format!("label=io.podman.compose.project={name}")]),
+        podman.secrets(),
     )
     .await?;
 
-    let mut containers = serde_json::from_str::<Vec<Container>>(&containers)?
+    let mut containers = containers
         .into_iter()
         .filter_map(|mut container| {
             container
@@ -127,7 +181,7 @@ pub(crate) async fn run(
     let all_containers = containers.len();
     containers.retain(|service, _| args.remove_orphans || file.services.keys().contains(&service));
 
-    let networks = serde_json::from_str::<Vec<Network>>(&networks)?
+    let networks = networks
         .into_iter()
         .filter_map(|network| {
             if args.remove_orphans
@@ -143,7 +197,7 @@ pub(crate) async fn run(
             }
         })
         .collect::<Vec<_>>();
-    let volumes = serde_json::from_str::<Vec<Volume>>(&volumes)?
+    let volumes = volumes
         .into_iter()
         .filter_map(|volume| {
             if args.remove_orphans
@@ -159,6 +213,44 @@ pub(crate) async fn run(
             }
         })
         .collect::<Vec<_>>();
+    let (secrets, configs): (Vec<_>, Vec<_>) = secrets
+        .into_iter()
+        .filter_map(|secret| {
+            let labels = secret.spec.labels?;
+
+            if labels.project.as_ref() != Some(name) {
+                return None;
+            }
+
+            if labels.config.is_some() {
+                (args.remove_orphans
+                    || file
+                        .configs
+                        .values()
+                        .filter_map(|config| config.name.as_ref())
+                        .contains(&secret.spec.name))
+                .then_some(Either::Right(secret.spec.name))
+            } else {
+                (args.remove_orphans
+                    || file
+                        .secrets
+                        .values()
+                        .filter_map(|secret| secret.name.as_ref())
+                        .contains(&secret.spec.name))
+                .then_some(Either::Left(secret.spec.name))
+            }
+        })
+        .partition_map(|secret| secret);
+    let images = file
+        .services
+        .iter()
+        .filter(|(_, service)| match args.rmi {
+            Some(ImageType::All) => true,
+            Some(ImageType::Local) => service.image.is_none(),
+            None => false,
+        })
+        .map(|(name, _)| file.image_name(name))
+        .collect::<IndexSet<_>>();
 
     if !containers.is_empty() {
         let progress = Progress::new(config);
@@ -197,14 +289,21 @@ pub(crate) async fn run(
     }
 
     if all_containers == containers.len() {
-        podman.run(["pod", "rm", "--ignore", name]).await?;
+        podman.remove_pod(name).await?;
     }
 
-    if !networks.is_empty() || (args.volumes && !volumes.is_empty()) || args.rmi {
+    if !networks.is_empty()
+        || (args.volumes && !volumes.is_empty())
+        || !secrets.is_empty()
+        || !configs.is_empty()
+        || !images.is_empty()
+    {
         let progress = Progress::new(config);
 
-        try_join!(
+        let ((), (), (), (), skipped) = try_join!(
             remove_networks(podman, &progress, &networks),
+            remove_secrets(podman, &progress, &secrets),
+            remove_configs(podman, &progress, &configs),
             async {
                 if args.volumes {
                     remove_volumes(podman, &progress, &volumes).await?;
@@ -212,28 +311,210 @@ pub(crate) async fn run(
 
                 Ok(())
             },
-            async {
-                if args.rmi {
-                    let spinner = progress.add_spinner("Images", "Removing");
-
-                    podman
-                        .run([
-                            "image",
-                            "prune",
-                            "--force",
-                            "--filter",
-                            &format!("label=io.podman.compose.project={name}"),
-                        ])
-                        .await
-                        .finish_with_message(spinner, "Removed")?;
-                }
-
-                Ok(())
-            }
+            remove_images(podman, &progress, &images),
         )?;
 
         progress.finish();
+
+        for image in skipped {
+            eprintln!(
+                "{} Image \"{image}\" is still used by other containers",
+                *STYLED_WARNING
+            );
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        commands::{create, start},
+        podman::fake::{self, FakeContainer, Operation},
+    };
+
+    fn seed_images(engine: &fake::Fake, file: &Compose) {
+        let mut state = engine.state_mut();
+
+        for service in file.services.keys() {
+            let image = file.image_name(service);
+            let id = format!("sha256:{service}");
+            state.images.insert(image, id);
+        }
+    }
+
+    fn removed_images(engine: &fake::Fake) -> Vec<String> {
+        let mut images = engine.targets(Operation::RemoveImage);
+        images.sort();
+
+        images
+    }
+
+    #[tokio::test]
+    async fn dependencies() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        start::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::Stop),
+            [format!("{name}_app_1"), format!("{name}_dependency_1")]
+        );
+
+        let state = engine.state();
+        assert!(state.containers.is_empty());
+        assert!(state.pods.is_empty());
+        assert!(state.networks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn networks() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/network-test/compose.yaml");
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        assert!(!engine.state().networks.is_empty());
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        let state = engine.state();
+        assert!(state.containers.is_empty());
+        assert!(state.networks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn orphans() {
+        let (config, mut file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.clone().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        file.services.shift_remove("app");
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        assert_eq!(
+            engine.state().containers.keys().collect::<Vec<_>>(),
+            [&format!("{name}_app_1")]
+        );
+        assert!(!engine.state().pods.is_empty());
+
+        run(fake::args(&["--remove-orphans"]), &podman, &file, &config)
+            .await
+            .unwrap();
+        assert!(engine.state().containers.is_empty());
+        assert!(engine.state().pods.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rmi_local() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/rmi/compose.yaml");
+        seed_images(&engine, &file);
+
+        run(fake::args(&["--rmi", "local"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(removed_images(&engine), [file.image_name("app")]);
+        assert_eq!(engine.state().images.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rmi_all() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/rmi/compose.yaml");
+        seed_images(&engine, &file);
+
+        run(fake::args(&["--rmi", "all"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            removed_images(&engine),
+            [
+                file.image_name("app"),
+                String::from("docker.io/library/postgres:16"),
+                String::from("rmi-test/tagged:latest"),
+            ]
+        );
+        assert!(engine.state().images.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rmi_in_use() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/rmi/compose.yaml");
+        seed_images(&engine, &file);
+        engine.state_mut().containers.insert(
+            String::from("other_db_1"),
+            FakeContainer {
+                pod: String::from("other"),
+                image: String::from("docker.io/library/postgres:16"),
+                labels: IndexMap::new(),
+                state: String::from("running"),
+                exit_code: 0,
+                health: None,
+            },
+        );
+
+        run(fake::args(&["--rmi", "all"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            removed_images(&engine),
+            [
+                file.image_name("app"),
+                String::from("rmi-test/tagged:latest")
+            ]
+        );
+        assert_eq!(
+            engine.state().images.keys().collect::<Vec<_>>(),
+            ["docker.io/library/postgres:16"]
+        );
+    }
+
+    #[tokio::test]
+    async fn orphan_secrets() {
+        let (config, mut file, engine, podman) =
+            fake::setup("tests/fixtures/env-secret/compose.yaml");
+        let secret = file.secrets["bar"].name.clone().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        engine
+            .state_mut()
+            .secrets
+            .insert(String::from("external"), IndexMap::new());
+        file.secrets.shift_remove("bar");
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+        assert_eq!(
+            engine.state().secrets.keys().collect::<Vec<_>>(),
+            [&secret, "external"]
+        );
+
+        run(fake::args(&["--remove-orphans"]), &podman, &file, &config)
+            .await
+            .unwrap();
+        assert_eq!(
+            engine.state().secrets.keys().collect::<Vec<_>>(),
+            ["external"]
+        );
+    }
+}
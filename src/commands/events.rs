@@ -3,10 +3,7 @@ use futures::TryStreamExt;
 use indexmap::IndexSet;
 use itertools::Itertools;
 
-use crate::{
-    compose::types::Compose,
-    podman::{types::Container, Podman},
-};
+use crate::{compose::types::Compose, podman::Podman};
 
 /// Receive real time events from containers
 #[derive(clap::Args, Debug)]
@@ -20,19 +17,12 @@ pub(crate) struct Args {
 }
 
 pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<()> {
-    let output = podman
-        .force_run([
-            "ps",
-            "--all",
-            "--format",
-            "json",
-            "--filter",
+    let services = podman
+        .containers([
             "label=io.podman.compose.oneoff=false",
-            "--filter",
             &format!("pod={}", file.name.as_ref().unwrap()),
         ])
-        .await?;
-    let services = serde_json::from_str::<Vec<Container>>(&output)?
+        .await?
         .into_iter()
         .filter_map(|container| {
             container
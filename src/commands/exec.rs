@@ -3,10 +3,7 @@ use std::path::PathBuf;
 use anyhow::{anyhow, Result};
 use atty::Stream;
 
-use crate::{
-    compose::types::Compose,
-    podman::{types::Container, Podman},
-};
+use crate::{compose::types::Compose, podman::Podman};
 
 /// Execute a command in a running container
 #[derive(clap::Args, Debug)]
@@ -46,30 +43,19 @@ pub(crate) struct Args {
 }
 
 pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<()> {
-    let output = podman
-        .force_run([
-            "ps",
-            "--all",
-            "--format",
-            "json",
-            "--filter",
+    let container = podman
+        .containers([
             "label=io.podman.compose.oneoff=false",
-            "--filter",
             &format!("pod={}", file.name.as_ref().unwrap()),
         ])
-        .await?;
-    let container = serde_json::from_str::<Vec<Container>>(&output)?
+        .await?
         .into_iter()
         .find_map(|mut container| {
             container.labels.and_then(|labels| {
                 if labels
                     .service
-                    .map(|service| args.service == service)
-                    .unwrap_or_default()
-                    && labels
-                        .container_number
-                        .map(|n| n == args.index)
-                        .unwrap_or_default()
+                    .is_some_and(|service| args.service == service)
+                    && labels.container_number.is_some_and(|n| n == args.index)
                 {
                     container.names.pop_front()
                 } else {
@@ -0,0 +1,29 @@
+automod::dir!(pub(crate) "src/commands/generate");
+
+use anyhow::Result;
+use clap::Subcommand;
+
+use crate::{compose, config::Config};
+
+/// Generate deployment files from the Compose file
+#[derive(clap::Args, Debug)]
+#[command(next_display_order = None)]
+pub(crate) struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Kube(kube::Args),
+    Quadlet(quadlet::Args),
+}
+
+pub(crate) fn run(args: Args, config: &Config) -> Result<()> {
+    let file = compose::parse(config, false, &[])?;
+
+    match args.command {
+        Command::Kube(args) => kube::run(args, &file),
+        Command::Quadlet(args) => quadlet::run(args, &file),
+    }
+}
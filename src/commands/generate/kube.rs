@@ -0,0 +1,1246 @@
+use std::{env, fs, path::PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+
+use crate::{
+    compose::types::{Compose, Healthcheck, PullPolicy, RestartPolicy, Service, ServiceVolumeType},
+    utils::STYLED_WARNING,
+};
+
+/// Generate Kubernetes manifests playable with `podman kube play`
+#[derive(clap::Args, Debug)]
+#[command(next_display_order = None)]
+pub(crate) struct Args {
+    /// File to write the manifests to (default to stdout)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Manifest {
+    api_version: &'static str,
+    kind: &'static str,
+    metadata: Metadata,
+    #[serde(flatten)]
+    content: Content,
+}
+
+#[serde_with::apply(
+    IndexMap => #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+)]
+#[derive(Serialize, Default, Debug)]
+struct Metadata {
+    name: String,
+    labels: IndexMap<String, String>,
+    annotations: IndexMap<String, String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum Content {
+    Pod {
+        spec: PodSpec,
+    },
+    Deployment {
+        spec: DeploymentSpec,
+    },
+    Service {
+        spec: ServiceSpec,
+    },
+    PersistentVolumeClaim {
+        spec: PersistentVolumeClaimSpec,
+    },
+    ConfigMap {
+        data: IndexMap<String, String>,
+    },
+    Secret {
+        r#type: &'static str,
+        data: IndexMap<String, String>,
+    },
+}
+
+#[skip_serializing_none]
+#[serde_with::apply(
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
+)]
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PodSpec {
+    hostname: Option<String>,
+    restart_policy: &'static str,
+    termination_grace_period_seconds: Option<u64>,
+    security_context: Option<PodSecurityContext>,
+    host_aliases: Vec<HostAlias>,
+    dns_config: Option<DnsConfig>,
+    containers: Vec<Container>,
+    volumes: Vec<Volume>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PodSecurityContext {
+    supplemental_groups: Vec<i64>,
+}
+
+#[derive(Serialize, Debug)]
+struct HostAlias {
+    ip: String,
+    hostnames: Vec<String>,
+}
+
+#[serde_with::apply(
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
+)]
+#[derive(Serialize, Debug)]
+struct DnsConfig {
+    nameservers: Vec<String>,
+    searches: Vec<String>,
+    options: Vec<DnsOption>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Debug)]
+struct DnsOption {
+    name: String,
+    value: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DeploymentSpec {
+    replicas: u32,
+    selector: Selector,
+    template: PodTemplate,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Selector {
+    match_labels: IndexMap<String, String>,
+}
+
+#[derive(Serialize, Debug)]
+struct PodTemplate {
+    metadata: Metadata,
+    spec: PodSpec,
+}
+
+#[skip_serializing_none]
+#[serde_with::apply(
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
+)]
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Container {
+    name: String,
+    image: String,
+    image_pull_policy: Option<&'static str>,
+    command: Vec<String>,
+    args: Vec<String>,
+    working_dir: Option<String>,
+    env: Vec<EnvVar>,
+    ports: Vec<ContainerPort>,
+    volume_mounts: Vec<VolumeMount>,
+    liveness_probe: Option<Probe>,
+    resources: Option<ResourceRequirements>,
+    security_context: Option<SecurityContext>,
+    stdin: Option<bool>,
+    tty: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+struct EnvVar {
+    name: String,
+    value: String,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ContainerPort {
+    container_port: u16,
+    host_port: Option<u16>,
+    #[serde(rename = "hostIP")]
+    host_ip: Option<String>,
+    protocol: String,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct VolumeMount {
+    name: String,
+    mount_path: String,
+    sub_path: Option<String>,
+    read_only: Option<bool>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Probe {
+    exec: ExecAction,
+    initial_delay_seconds: Option<u64>,
+    period_seconds: Option<u64>,
+    timeout_seconds: Option<u64>,
+    failure_threshold: Option<u32>,
+}
+
+#[derive(Serialize, Debug)]
+struct ExecAction {
+    command: Vec<String>,
+}
+
+#[serde_with::apply(
+    IndexMap => #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+)]
+#[derive(Serialize, Debug)]
+struct ResourceRequirements {
+    limits: IndexMap<&'static str, String>,
+    requests: IndexMap<&'static str, String>,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SecurityContext {
+    privileged: Option<bool>,
+    read_only_root_filesystem: Option<bool>,
+    run_as_user: Option<i64>,
+    run_as_group: Option<i64>,
+    capabilities: Option<Capabilities>,
+}
+
+#[serde_with::apply(
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
+)]
+#[derive(Serialize, Debug)]
+struct Capabilities {
+    add: Vec<String>,
+    drop: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct Volume {
+    name: String,
+    #[serde(flatten)]
+    source: VolumeSource,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+enum VolumeSource {
+    #[serde(rename_all = "camelCase")]
+    PersistentVolumeClaim {
+        claim_name: String,
+    },
+    HostPath {
+        path: String,
+    },
+    EmptyDir {
+        medium: Option<&'static str>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Secret {
+        secret_name: String,
+    },
+    ConfigMap {
+        name: String,
+    },
+}
+
+#[derive(Serialize, Debug)]
+struct ServiceSpec {
+    selector: IndexMap<String, String>,
+    ports: Vec<ServicePort>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ServicePort {
+    name: String,
+    port: u16,
+    target_port: u16,
+    protocol: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PersistentVolumeClaimSpec {
+    access_modes: Vec<&'static str>,
+    resources: VolumeResourceRequirements,
+}
+
+#[derive(Serialize, Debug)]
+struct VolumeResourceRequirements {
+    requests: IndexMap<&'static str, &'static str>,
+}
+
+/// Turn a Compose name into a valid Kubernetes resource name
+fn resource_name(name: &str) -> String {
+    name.to_lowercase().replace(['_', '.'], "-")
+}
+
+/// Make sure that distinct Compose names stay distinct and short enough as resource names
+fn check_names<I, S>(kind: &str, names: I) -> Result<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut resources = IndexMap::new();
+
+    for name in names {
+        let name = name.as_ref().to_owned();
+        let resource = resource_name(&name);
+
+        if resource.len() > 63 {
+            bail!("{kind} name \"{resource}\" is longer than 63 characters");
+        }
+
+        if let Some(other) = resources.get(&resource) {
+            if *other != name {
+                bail!("{kind} names \"{other}\" and \"{name}\" both become \"{resource}\"");
+            }
+        }
+
+        resources.insert(resource, name);
+    }
+
+    Ok(())
+}
+
+fn selector(project_name: &str, service_name: &str) -> IndexMap<String, String> {
+    IndexMap::from([
+        (
+            String::from("io.podman.compose.project"),
+            project_name.to_owned(),
+        ),
+        (
+            String::from("io.podman.compose.service"),
+            service_name.to_owned(),
+        ),
+    ])
+}
+
+fn probe(healthcheck: &Healthcheck) -> Option<Probe> {
+    if healthcheck.disable.unwrap_or_default() {
+        return None;
+    }
+
+    let command = match healthcheck
+        .test
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] | ["NONE", ..] => return None,
+        ["CMD", command @ ..] => command.iter().map(|arg| (*arg).to_owned()).collect(),
+        ["CMD-SHELL", command @ ..] => vec![
+            String::from("/bin/sh"),
+            String::from("-c"),
+            command.join(" "),
+        ],
+        _ => healthcheck.test.clone(),
+    };
+
+    Some(Probe {
+        exec: ExecAction { command },
+        initial_delay_seconds: healthcheck
+            .start_period
+            .map(|start_period| start_period.as_secs()),
+        period_seconds: healthcheck.interval.map(|interval| interval.as_secs()),
+        timeout_seconds: healthcheck.timeout.map(|timeout| timeout.as_secs()),
+        failure_threshold: healthcheck.retries,
+    })
+}
+
+fn resources(
+    service: &Service,
+    service_name: &str,
+    warnings: &mut Vec<String>,
+) -> Option<ResourceRequirements> {
+    let resources = service
+        .deploy
+        .as_ref()
+        .and_then(|deploy| deploy.resources.as_ref());
+    let limits = resources.and_then(|resources| resources.limits.as_ref());
+    let reservations = resources.and_then(|resources| resources.reservations.as_ref());
+    let mut requirements = ResourceRequirements {
+        limits: IndexMap::new(),
+        requests: IndexMap::new(),
+    };
+
+    if let Some(cpus) = limits.and_then(|limits| limits.cpus).or(service.cpus) {
+        requirements.limits.insert("cpu", cpus.to_string());
+    }
+
+    if let Some(memory) = limits
+        .and_then(|limits| limits.memory)
+        .or(service.mem_limit)
+    {
+        requirements
+            .limits
+            .insert("memory", memory.as_u64().to_string());
+    }
+
+    if let Some(cpus) = reservations.and_then(|reservations| reservations.cpus) {
+        requirements.requests.insert("cpu", cpus.to_string());
+    }
+
+    if let Some(memory) = reservations
+        .and_then(|reservations| reservations.memory)
+        .or(service.mem_reservation)
+    {
+        requirements
+            .requests
+            .insert("memory", memory.as_u64().to_string());
+    }
+
+    if limits.and_then(|limits| limits.pids).is_some() || service.pids_limit.is_some() {
+        warnings.push(format!(
+            "Service \"{service_name}\" has a PIDs limit, which is ignored"
+        ));
+    }
+
+    if requirements.limits.is_empty() && requirements.requests.is_empty() {
+        None
+    } else {
+        Some(requirements)
+    }
+}
+
+fn security_context(
+    service: &Service,
+    service_name: &str,
+    warnings: &mut Vec<String>,
+) -> Option<SecurityContext> {
+    let mut context = SecurityContext {
+        privileged: service.privileged,
+        read_only_root_filesystem: service.read_only,
+        ..SecurityContext::default()
+    };
+
+    if let Some(user) = &service.user {
+        let (uid, gid) = user
+            .split_once(':')
+            .map_or((user.as_str(), None), |(uid, gid)| (uid, Some(gid)));
+
+        if let (Ok(uid), Ok(gid)) = (uid.parse(), gid.map(str::parse).transpose()) {
+            context.run_as_user = Some(uid);
+            context.run_as_group = gid;
+        } else {
+            warnings.push(format!(
+                "User \"{user}\" of service \"{service_name}\" is ignored as it is not numeric"
+            ));
+        }
+    }
+
+    if !service.cap_add.is_empty() || !service.cap_drop.is_empty() {
+        context.capabilities = Some(Capabilities {
+            add: service.cap_add.clone(),
+            drop: service.cap_drop.clone(),
+        });
+    }
+
+    if context.privileged.is_none()
+        && context.read_only_root_filesystem.is_none()
+        && context.run_as_user.is_none()
+        && context.capabilities.is_none()
+    {
+        None
+    } else {
+        Some(context)
+    }
+}
+
+fn pod_security_context(
+    service: &Service,
+    service_name: &str,
+    warnings: &mut Vec<String>,
+) -> Option<PodSecurityContext> {
+    let supplemental_groups = service
+        .group_add
+        .iter()
+        .filter_map(|group| {
+            if let Ok(group) = group.parse() {
+                Some(group)
+            } else {
+                warnings.push(format!(
+                    "Group \"{group}\" of service \"{service_name}\" is ignored as it is not numeric"
+                ));
+
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if supplemental_groups.is_empty() {
+        None
+    } else {
+        Some(PodSecurityContext {
+            supplemental_groups,
+        })
+    }
+}
+
+fn environment(
+    service: &Service,
+    service_name: &str,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<EnvVar>> {
+    let mut environment = IndexMap::new();
+
+    for env_file in &service.env_file {
+        for item in dotenvy::from_path_iter(env_file)
+            .with_context(|| format!("{} not found", env_file.display()))?
+        {
+            let (key, value) = item?;
+            environment.insert(key, Some(value));
+        }
+    }
+
+    environment.extend(service.environment.clone());
+
+    Ok(environment
+        .into_iter()
+        .filter_map(|(name, value)| {
+            if let Some(value) = value {
+                Some(EnvVar { name, value })
+            } else {
+                warnings.push(format!(
+                    "Service \"{service_name}\" has no value for environment variable \"{name}\""
+                ));
+
+                None
+            }
+        })
+        .collect())
+}
+
+fn ports(
+    service: &Service,
+    service_name: &str,
+    warnings: &mut Vec<String>,
+) -> (Vec<ContainerPort>, Vec<ServicePort>) {
+    let mut container_ports = Vec::new();
+    let mut service_ports = Vec::new();
+
+    for port in &service.ports {
+        let Ok(container_port) = port.target.parse() else {
+            warnings.push(format!(
+                "Service \"{service_name}\" publishes port range \"{port}\", which is ignored"
+            ));
+            continue;
+        };
+        let host_port = match port.published.as_deref().map(str::parse) {
+            Some(Ok(host_port)) => Some(host_port),
+            Some(Err(_)) => {
+                warnings.push(format!(
+                    "Service \"{service_name}\" publishes port \"{port}\" on a single host port"
+                ));
+                None
+            }
+            None => None,
+        };
+        let protocol = port.protocol.to_uppercase();
+        let service_port = host_port.unwrap_or(container_port);
+
+        // Named after the Service port as the same container port can be published more than once
+        service_ports.push(ServicePort {
+            name: format!("{}-{service_port}", port.protocol),
+            port: service_port,
+            target_port: container_port,
+            protocol: protocol.clone(),
+        });
+        container_ports.push(ContainerPort {
+            container_port,
+            host_port,
+            host_ip: port.host_ip.clone(),
+            protocol,
+        });
+    }
+
+    (container_ports, service_ports)
+}
+
+fn secret_data(
+    name: &str,
+    file: Option<&PathBuf>,
+    environment: Option<&String>,
+    content: Option<&String>,
+) -> Result<String> {
+    Ok(if let Some(content) = content {
+        content.clone()
+    } else if let Some(environment) = environment {
+        env::var(environment).with_context(|| {
+            anyhow!("Environment variable \"{environment}\" of \"{name}\" is not set")
+        })?
+    } else if let Some(file) = file {
+        fs::read_to_string(file).with_context(|| anyhow!("{} cannot be read", file.display()))?
+    } else {
+        bail!("\"{name}\" has no file, environment or content");
+    })
+}
+
+fn workload(
+    file: &Compose,
+    service_name: &str,
+    service: &Service,
+    warnings: &mut Vec<String>,
+) -> Result<(Manifest, Vec<ServicePort>)> {
+    let project_name = file.name.as_ref().unwrap();
+    let (ports, service_ports) = ports(service, service_name, warnings);
+    let mut volumes = Vec::<Volume>::new();
+    let mut volume_mounts = Vec::new();
+
+    for (i, service_volume) in service.volumes.iter().enumerate() {
+        let name = match &service_volume.r#type {
+            ServiceVolumeType::Volume(Some(source)) => {
+                let name = resource_name(file.volumes[source].name.as_ref().unwrap());
+
+                if !volumes.iter().any(|volume| volume.name == name) {
+                    volumes.push(Volume {
+                        name: name.clone(),
+                        source: VolumeSource::PersistentVolumeClaim {
+                            claim_name: name.clone(),
+                        },
+                    });
+                }
+
+                name
+            }
+            r#type => {
+                let name = format!("{}-{i}", resource_name(service_name));
+
+                volumes.push(Volume {
+                    name: name.clone(),
+                    source: match r#type {
+                        ServiceVolumeType::Bind(source) => VolumeSource::HostPath {
+                            path: source.to_string_lossy().to_string(),
+                        },
+                        ServiceVolumeType::Tmpfs => VolumeSource::EmptyDir {
+                            medium: Some("Memory"),
+                        },
+                        ServiceVolumeType::Volume(_) => VolumeSource::EmptyDir { medium: None },
+                    },
+                });
+
+                name
+            }
+        };
+
+        volume_mounts.push(VolumeMount {
+            name,
+            mount_path: service_volume.target.to_string_lossy().to_string(),
+            sub_path: None,
+            read_only: service_volume.read_only,
+        });
+    }
+
+    for (i, tmpfs) in service.tmpfs.iter().enumerate() {
+        let name = format!("{}-tmpfs-{i}", resource_name(service_name));
+
+        volumes.push(Volume {
+            name: name.clone(),
+            source: VolumeSource::EmptyDir {
+                medium: Some("Memory"),
+            },
+        });
+        volume_mounts.push(VolumeMount {
+            name,
+            mount_path: tmpfs.to_string_lossy().to_string(),
+            sub_path: None,
+            read_only: None,
+        });
+    }
+
+    for secret in &service.secrets {
+        let name = resource_name(file.secrets[&secret.source].name.as_ref().unwrap());
+
+        if !volumes.iter().any(|volume| volume.name == name) {
+            volumes.push(Volume {
+                name: name.clone(),
+                source: VolumeSource::Secret {
+                    secret_name: name.clone(),
+                },
+            });
+        }
+
+        let target = secret
+            .target
+            .clone()
+            .unwrap_or_else(|| secret.source.clone());
+
+        volume_mounts.push(VolumeMount {
+            name,
+            mount_path: if target.starts_with('/') {
+                target
+            } else {
+                format!("/run/secrets/{target}")
+            },
+            sub_path: Some(secret.source.clone()),
+            read_only: Some(true),
+        });
+    }
+
+    for config in &service.configs {
+        let name = resource_name(file.configs[&config.source].name.as_ref().unwrap());
+
+        if !volumes.iter().any(|volume| volume.name == name) {
+            volumes.push(Volume {
+                name: name.clone(),
+                source: VolumeSource::ConfigMap { name: name.clone() },
+            });
+        }
+
+        volume_mounts.push(VolumeMount {
+            name,
+            mount_path: config
+                .target
+                .clone()
+                .unwrap_or_else(|| format!("/{}", config.source)),
+            sub_path: Some(config.source.clone()),
+            read_only: Some(true),
+        });
+    }
+
+    let replicas = service.replicas();
+    let restart_policy = match service.restart {
+        None | Some(RestartPolicy::No) => "Never",
+        Some(RestartPolicy::Always | RestartPolicy::UnlessStopped) => "Always",
+        Some(RestartPolicy::OnFailure) => "OnFailure",
+    };
+
+    if replicas > 1 && restart_policy != "Always" {
+        warnings.push(format!(
+            "Service \"{service_name}\" always restarts as a Deployment"
+        ));
+    }
+
+    let mut host_aliases = Vec::<HostAlias>::new();
+
+    for (hostname, ip) in &service.extra_hosts {
+        if let Some(host_alias) = host_aliases
+            .iter_mut()
+            .find(|host_alias| host_alias.ip == *ip)
+        {
+            host_alias.hostnames.push(hostname.clone());
+        } else {
+            host_aliases.push(HostAlias {
+                ip: ip.clone(),
+                hostnames: vec![hostname.clone()],
+            });
+        }
+    }
+
+    let dns_config =
+        if service.dns.is_empty() && service.dns_search.is_empty() && service.dns_opt.is_empty() {
+            None
+        } else {
+            Some(DnsConfig {
+                nameservers: service.dns.clone(),
+                searches: service.dns_search.clone(),
+                options: service
+                    .dns_opt
+                    .iter()
+                    .map(|option| {
+                        let (name, value) = option
+                            .split_once(':')
+                            .map_or((option.clone(), None), |(name, value)| {
+                                (name.to_owned(), Some(value.to_owned()))
+                            });
+
+                        DnsOption { name, value }
+                    })
+                    .collect(),
+            })
+        };
+
+    let spec = PodSpec {
+        hostname: service.hostname.clone(),
+        restart_policy: if replicas > 1 {
+            "Always"
+        } else {
+            restart_policy
+        },
+        termination_grace_period_seconds: service
+            .stop_grace_period
+            .map(|stop_grace_period| stop_grace_period.as_secs()),
+        security_context: pod_security_context(service, service_name, warnings),
+        host_aliases,
+        dns_config,
+        containers: vec![Container {
+            name: resource_name(service_name),
+            image: file.image_name(service_name),
+            image_pull_policy: match service.pull_policy {
+                // `podman kube play` pulls newer images with the `Always` policy
+                Some(PullPolicy::Always | PullPolicy::Newer) => Some("Always"),
+                Some(PullPolicy::Missing) => Some("IfNotPresent"),
+                Some(PullPolicy::Never) => Some("Never"),
+                Some(PullPolicy::Build) | None => None,
+            },
+            command: service.entrypoint.clone(),
+            args: service.command.clone(),
+            working_dir: service
+                .working_dir
+                .as_ref()
+                .map(|working_dir| working_dir.to_string_lossy().to_string()),
+            env: environment(service, service_name, warnings)?,
+            ports,
+            volume_mounts,
+            liveness_probe: service.healthcheck.as_ref().and_then(probe),
+            resources: resources(service, service_name, warnings),
+            security_context: security_context(service, service_name, warnings),
+            stdin: service.stdin_open,
+            tty: service.tty,
+        }],
+        volumes,
+    };
+    let metadata = Metadata {
+        name: resource_name(&format!("{project_name}-{service_name}")),
+        labels: selector(project_name, service_name),
+        annotations: service.labels.clone(),
+    };
+
+    let manifest = if replicas > 1 {
+        warnings.push(format!(
+            "Service \"{service_name}\" has {replicas} replicas, `podman kube play` only starts one"
+        ));
+
+        Manifest {
+            api_version: "apps/v1",
+            kind: "Deployment",
+            metadata: Metadata {
+                name: metadata.name.clone(),
+                labels: metadata.labels.clone(),
+                annotations: IndexMap::new(),
+            },
+            content: Content::Deployment {
+                spec: DeploymentSpec {
+                    replicas,
+                    selector: Selector {
+                        match_labels: metadata.labels.clone(),
+                    },
+                    template: PodTemplate { metadata, spec },
+                },
+            },
+        }
+    } else {
+        Manifest {
+            api_version: "v1",
+            kind: "Pod",
+            metadata,
+            content: Content::Pod { spec },
+        }
+    };
+
+    Ok((manifest, service_ports))
+}
+
+fn lossy_fields(service: &Service) -> Vec<&'static str> {
+    [
+        ("blkio_config", service.blkio_config.is_some()),
+        ("build", service.build.is_some()),
+        ("cgroup_parent", service.cgroup_parent.is_some()),
+        ("container_name", service.container_name.is_some()),
+        ("cpu_shares", service.cpu_shares.is_some()),
+        ("cpuset", service.cpuset.is_some()),
+        ("depends_on", !service.depends_on.is_empty()),
+        ("develop", service.develop.is_some()),
+        (
+            "device_cgroup_rules",
+            !service.device_cgroup_rules.is_empty(),
+        ),
+        ("devices", !service.devices.is_empty()),
+        ("expose", !service.expose.is_empty()),
+        ("init", service.init.is_some()),
+        ("ipc", service.ipc.is_some()),
+        ("logging", service.logging.is_some()),
+        ("mem_swappiness", service.mem_swappiness.is_some()),
+        ("network_mode", service.network_mode.is_some()),
+        (
+            "networks",
+            service.networks.keys().any(|network| network != "default"),
+        ),
+        ("oom_score_adj", service.oom_score_adj.is_some()),
+        ("pid", service.pid.is_some()),
+        ("platform", service.platform.is_some()),
+        ("security_opt", !service.security_opt.is_empty()),
+        ("shm_size", service.shm_size.is_some()),
+        ("stop_signal", service.stop_signal.is_some()),
+        ("storage_opt", !service.storage_opt.is_empty()),
+        ("sysctls", !service.sysctls.is_empty()),
+        ("ulimits", !service.ulimits.is_empty()),
+        ("userns_mode", service.userns_mode.is_some()),
+        ("volumes_from", !service.volumes_from.is_empty()),
+    ]
+    .into_iter()
+    .filter_map(|(field, set)| set.then_some(field))
+    .collect()
+}
+
+pub(crate) fn manifests(file: &Compose) -> Result<(Vec<Manifest>, Vec<String>)> {
+    let project_name = file.name.as_ref().unwrap();
+    let mut manifests = Vec::new();
+    let mut warnings = Vec::new();
+
+    check_names(
+        "Volume",
+        file.volumes
+            .values()
+            .filter(|volume| !volume.external.unwrap_or_default())
+            .filter_map(|volume| volume.name.as_ref()),
+    )?;
+    check_names(
+        "Config",
+        file.configs
+            .values()
+            .filter(|config| !config.external.unwrap_or_default())
+            .filter_map(|config| config.name.as_ref()),
+    )?;
+    check_names(
+        "Secret",
+        file.secrets
+            .values()
+            .filter(|secret| !secret.external.unwrap_or_default())
+            .filter_map(|secret| secret.name.as_ref()),
+    )?;
+    check_names("Service", file.services.keys())?;
+    check_names(
+        "Workload",
+        file.services
+            .keys()
+            .map(|service_name| format!("{project_name}-{service_name}")),
+    )?;
+
+    for volume in file.volumes.values() {
+        if volume.external.unwrap_or_default() {
+            continue;
+        }
+
+        let mut annotations = IndexMap::new();
+
+        if let Some(driver) = &volume.driver {
+            annotations.insert(String::from("volume.podman.io/driver"), driver.clone());
+        }
+
+        for (key, value) in &volume.driver_opts {
+            let key = match key.as_str() {
+                "o" => "mount-options",
+                key => key,
+            };
+
+            annotations.insert(format!("volume.podman.io/{key}"), value.clone());
+        }
+
+        manifests.push(Manifest {
+            api_version: "v1",
+            kind: "PersistentVolumeClaim",
+            metadata: Metadata {
+                name: resource_name(volume.name.as_ref().unwrap()),
+                labels: IndexMap::from([(
+                    String::from("io.podman.compose.project"),
+                    project_name.clone(),
+                )]),
+                annotations,
+            },
+            content: Content::PersistentVolumeClaim {
+                spec: PersistentVolumeClaimSpec {
+                    access_modes: vec!["ReadWriteOnce"],
+                    resources: VolumeResourceRequirements {
+                        requests: IndexMap::from([("storage", "1Gi")]),
+                    },
+                },
+            },
+        });
+    }
+
+    for (name, config) in &file.configs {
+        if config.external.unwrap_or_default() {
+            continue;
+        }
+
+        manifests.push(Manifest {
+            api_version: "v1",
+            kind: "ConfigMap",
+            metadata: Metadata {
+                name: resource_name(config.name.as_ref().unwrap()),
+                ..Metadata::default()
+            },
+            content: Content::ConfigMap {
+                data: IndexMap::from([(
+                    name.clone(),
+                    secret_data(
+                        name,
+                        config.file.as_ref(),
+                        config.environment.as_ref(),
+                        config.content.as_ref(),
+                    )?,
+                )]),
+            },
+        });
+    }
+
+    for (name, secret) in &file.secrets {
+        if secret.external.unwrap_or_default() {
+            continue;
+        }
+
+        manifests.push(Manifest {
+            api_version: "v1",
+            kind: "Secret",
+            metadata: Metadata {
+                name: resource_name(secret.name.as_ref().unwrap()),
+                ..Metadata::default()
+            },
+            content: Content::Secret {
+                r#type: "Opaque",
+                data: IndexMap::from([(
+                    name.clone(),
+                    STANDARD.encode(secret_data(
+                        name,
+                        secret.file.as_ref(),
+                        secret.environment.as_ref(),
+                        None,
+                    )?),
+                )]),
+            },
+        });
+    }
+
+    for (service_name, service) in &file.services {
+        let (manifest, ports) = workload(file, service_name, service, &mut warnings)?;
+        manifests.push(manifest);
+
+        if !ports.is_empty() {
+            manifests.push(Manifest {
+                api_version: "v1",
+                kind: "Service",
+                metadata: Metadata {
+                    name: resource_name(service_name),
+                    labels: selector(project_name, service_name),
+                    ..Metadata::default()
+                },
+                content: Content::Service {
+                    spec: ServiceSpec {
+                        selector: selector(project_name, service_name),
+                        ports,
+                    },
+                },
+            });
+        }
+
+        let fields = lossy_fields(service);
+
+        if !fields.is_empty() {
+            warnings.push(format!(
+                "Service \"{service_name}\" has no Kubernetes equivalent for {}",
+                fields.join(", ")
+            ));
+        }
+    }
+
+    Ok((manifests, warnings))
+}
+
+pub(crate) fn run(args: Args, file: &Compose) -> Result<()> {
+    let (manifests, warnings) = manifests(file)?;
+
+    for warning in warnings {
+        eprintln!("{} {warning}", *STYLED_WARNING);
+    }
+
+    let contents = manifests
+        .iter()
+        .map(serde_yaml::to_string)
+        .collect::<Result<Vec<_>, _>>()?
+        .join("---\n");
+
+    if let Some(output) = args.output {
+        fs::write(&output, contents)
+            .with_context(|| anyhow!("{} cannot be written", output.display()))?;
+    } else {
+        print!("{contents}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use path_absolutize::Absolutize;
+    use pretty_assertions::assert_eq;
+    use serde_yaml::Value;
+
+    use super::*;
+    use crate::{compose, config::Config};
+
+    fn parse() -> Compose {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/kube/compose.yaml")],
+            project_directory: Path::new("tests/fixtures/kube")
+                .absolutize()
+                .unwrap()
+                .to_path_buf(),
+            ..Config::default()
+        };
+
+        compose::parse(&config, false, &[]).unwrap()
+    }
+
+    #[test]
+    fn kube() {
+        let file = parse();
+        let name = file.name.as_ref().unwrap();
+        let (manifests, warnings) = manifests(&file).unwrap();
+        let manifests = manifests
+            .iter()
+            .map(|manifest| serde_yaml::to_value(manifest).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            manifests
+                .iter()
+                .map(|manifest| (
+                    manifest["kind"].as_str().unwrap(),
+                    manifest["metadata"]["name"].as_str().unwrap()
+                ))
+                .collect::<Vec<_>>(),
+            [
+                ("PersistentVolumeClaim", format!("{name}-data").as_str()),
+                ("ConfigMap", &format!("{name}-nginx")),
+                ("Secret", &format!("{name}-password")),
+                ("Pod", &format!("{name}-web")),
+                ("Service", "web"),
+                ("Deployment", &format!("{name}-db")),
+            ]
+        );
+
+        assert_eq!(manifests[1]["data"]["nginx"], "server {}\n");
+        assert_eq!(manifests[2]["data"]["password"], "c2VjcmV0Cg==");
+
+        let web = &manifests[3]["spec"]["containers"][0];
+        assert_eq!(
+            web["env"],
+            serde_yaml::from_str::<Value>("[{name: GREETING, value: hello}]").unwrap()
+        );
+        assert_eq!(web["ports"][0]["containerPort"], 80);
+        assert_eq!(web["ports"][0]["hostPort"], 8080);
+        assert_eq!(
+            web["volumeMounts"][0]["name"],
+            format!("{name}-data").as_str()
+        );
+        assert_eq!(web["volumeMounts"][0]["readOnly"], true);
+        assert_eq!(web["volumeMounts"][2]["subPath"], "nginx");
+        assert_eq!(
+            web["livenessProbe"]["exec"]["command"][2],
+            "wget -q -O - http://localhost"
+        );
+        assert_eq!(web["livenessProbe"]["periodSeconds"], 30);
+        assert_eq!(web["resources"]["limits"]["cpu"], "0.5");
+        assert_eq!(manifests[3]["spec"]["restartPolicy"], "Always");
+        assert_eq!(
+            manifests[3]["spec"]["volumes"][0]["persistentVolumeClaim"]["claimName"],
+            format!("{name}-data").as_str()
+        );
+        assert_eq!(
+            manifests[3]["spec"]["volumes"][1]["emptyDir"]["medium"],
+            "Memory"
+        );
+
+        assert_eq!(manifests[4]["spec"]["ports"][0]["name"], "tcp-8080");
+        assert_eq!(manifests[4]["spec"]["ports"][0]["port"], 8080);
+        assert_eq!(manifests[4]["spec"]["ports"][0]["targetPort"], 80);
+        assert_eq!(manifests[4]["spec"]["ports"][1]["name"], "tcp-8081");
+        assert_eq!(manifests[4]["spec"]["ports"][1]["port"], 8081);
+        assert_eq!(manifests[4]["spec"]["ports"][1]["targetPort"], 80);
+
+        assert_eq!(manifests[5]["spec"]["replicas"], 2);
+        let pod = &manifests[5]["spec"]["template"]["spec"];
+        assert_eq!(pod["terminationGracePeriodSeconds"], 30);
+        assert_eq!(
+            pod["securityContext"]["supplementalGroups"],
+            serde_yaml::from_str::<Value>("[1000]").unwrap()
+        );
+        let db = &pod["containers"][0];
+        assert_eq!(db["imagePullPolicy"], "IfNotPresent");
+        assert_eq!(db["securityContext"]["runAsUser"], 999);
+        assert_eq!(db["volumeMounts"][0]["mountPath"], "/run/secrets/password");
+
+        assert_eq!(
+            warnings,
+            [
+                "Service \"web\" has no value for environment variable \"UNSET\"",
+                "Service \"web\" has no Kubernetes equivalent for depends_on",
+                "Service \"db\" always restarts as a Deployment",
+                "Group \"wheel\" of service \"db\" is ignored as it is not numeric",
+                "Service \"db\" has 2 replicas, `podman kube play` only starts one",
+                "Service \"db\" has no Kubernetes equivalent for shm_size",
+            ]
+        );
+    }
+
+    #[test]
+    fn name_collision() {
+        let mut file = parse();
+        let web = file.services["web"].clone();
+        file.services.insert(String::from("WEB"), web);
+
+        assert_eq!(
+            manifests(&file).unwrap_err().to_string(),
+            "Service names \"web\" and \"WEB\" both become \"web\""
+        );
+    }
+
+    #[test]
+    fn long_name() {
+        let mut file = parse();
+        let web = file.services["web"].clone();
+        file.services.insert("a".repeat(64), web);
+
+        assert_eq!(
+            manifests(&file).unwrap_err().to_string(),
+            format!(
+                "Service name \"{}\" is longer than 63 characters",
+                "a".repeat(64)
+            )
+        );
+    }
+
+    #[test]
+    fn probe() {
+        let healthcheck = |test: &[&str]| Healthcheck {
+            test: test.iter().map(|arg| (*arg).to_owned()).collect(),
+            interval: None,
+            timeout: None,
+            start_period: None,
+            retries: None,
+            disable: None,
+        };
+
+        assert_eq!(
+            super::probe(&healthcheck(&["CMD", "pg_isready", "-U", "postgres"]))
+                .unwrap()
+                .exec
+                .command,
+            ["pg_isready", "-U", "postgres"]
+        );
+        assert_eq!(
+            super::probe(&healthcheck(&["curl", "-f", "http://localhost"]))
+                .unwrap()
+                .exec
+                .command,
+            ["curl", "-f", "http://localhost"]
+        );
+        assert!(super::probe(&healthcheck(&["NONE"])).is_none());
+    }
+}
@@ -0,0 +1,800 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs,
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, Context, Result};
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    compose::types::{
+        Compose, Condition, FileReference, PullPolicy, RestartPolicy, Service, ServiceVolume,
+        ServiceVolumeType,
+    },
+    utils::STYLED_WARNING,
+};
+
+/// Generate Quadlet unit files for systemd
+///
+/// Containers join the project's `.pod` unit, which Quadlet supports from Podman 5.0.
+#[derive(clap::Args, Debug)]
+#[command(next_display_order = None)]
+pub(crate) struct Args {
+    /// Directory to write the unit files to (default to stdout)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Default, Debug)]
+pub(crate) struct Unit {
+    sections: IndexMap<&'static str, Vec<(&'static str, String)>>,
+}
+
+impl Unit {
+    fn add(&mut self, section: &'static str, key: &'static str, value: impl Into<String>) {
+        self.sections
+            .entry(section)
+            .or_default()
+            .push((key, value.into().replace('%', "%%")));
+    }
+
+    #[cfg(test)]
+    fn get(&self, section: &str, key: &str) -> Vec<&str> {
+        self.sections
+            .get(section)
+            .into_iter()
+            .flatten()
+            .filter(|(name, _)| *name == key)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, (section, entries)) in self.sections.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            writeln!(f, "[{section}]")?;
+
+            for (key, value) in entries {
+                writeln!(f, "{key}={value}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn service_unit(project_name: &str, service_name: &str) -> String {
+    format!("{project_name}-{service_name}")
+}
+
+fn network(file: &Compose, name: &str) -> String {
+    let network = &file.networks[name];
+    let name = network.name.clone().unwrap();
+
+    if network.external.unwrap_or_default() {
+        name
+    } else {
+        format!("{name}.network")
+    }
+}
+
+fn volume(file: &Compose, name: &str) -> String {
+    let volume = &file.volumes[name];
+    let name = volume.name.clone().unwrap();
+
+    if volume.external.unwrap_or_default() {
+        name
+    } else {
+        format!("{name}.volume")
+    }
+}
+
+fn container(
+    file: &Compose,
+    service_name: &str,
+    service: &Service,
+    warnings: &mut Vec<String>,
+) -> Unit {
+    let project_name = file.name.as_ref().unwrap();
+    let mut unit = Unit::default();
+
+    unit.add(
+        "Unit",
+        "Description",
+        format!("Service {service_name} of project {project_name}"),
+    );
+
+    for (dependency, condition) in &service.depends_on {
+        let dependency_unit = format!("{}.service", service_unit(project_name, dependency));
+
+        if condition.required.unwrap_or(true) {
+            unit.add("Unit", "Requires", &dependency_unit);
+        } else {
+            unit.add("Unit", "Wants", &dependency_unit);
+        }
+
+        unit.add("Unit", "After", dependency_unit);
+
+        if condition.condition != Condition::Started {
+            warnings.push(format!(
+                "Service \"{service_name}\" waits for \"{dependency}\" to start instead of {}",
+                match condition.condition {
+                    Condition::Healthy => "becoming healthy",
+                    _ => "completing successfully",
+                }
+            ));
+        }
+    }
+
+    if service.build.is_some() || service.pull_policy == Some(PullPolicy::Build) {
+        warnings.push(format!(
+            "Image \"{}\" of service \"{service_name}\" must be built beforehand",
+            file.image_name(service_name)
+        ));
+    }
+
+    if service.replicas() > 1 {
+        warnings.push(format!(
+            "Service \"{service_name}\" is limited to one replica"
+        ));
+    }
+
+    if service.develop.is_some() {
+        warnings.push(format!(
+            "Service \"{service_name}\" has a develop section, which is ignored"
+        ));
+    }
+
+    let (container_number, container_name) = service
+        .container_names(project_name, service_name)
+        .next()
+        .unwrap();
+
+    unit.add("Container", "ContainerName", container_name);
+    unit.add("Container", "Image", file.image_name(service_name));
+    unit.add("Container", "Pod", format!("{project_name}.pod"));
+
+    if let Some(pull_policy) = &service.pull_policy {
+        if *pull_policy != PullPolicy::Build {
+            unit.add("Container", "Pull", pull_policy.to_string());
+        }
+    }
+
+    for (key, value) in [
+        ("project", project_name.as_str()),
+        ("service", service_name),
+        ("container-number", &container_number.to_string()),
+        ("oneoff", "false"),
+        ("config-hash", &file.config_hash(service_name, None)),
+    ] {
+        unit.add(
+            "Container",
+            "Label",
+            shell_words::quote(&format!("io.podman.compose.{key}={value}")),
+        );
+    }
+
+    for (key, value) in &service.labels {
+        unit.add(
+            "Container",
+            "Label",
+            shell_words::quote(&format!("{key}={value}")),
+        );
+    }
+
+    if !service.entrypoint.is_empty() {
+        unit.add(
+            "Container",
+            "Entrypoint",
+            serde_json::to_string(&service.entrypoint).unwrap(),
+        );
+    }
+
+    if !service.command.is_empty() {
+        unit.add("Container", "Exec", shell_words::join(&service.command));
+    }
+
+    for (key, value) in &service.environment {
+        unit.add(
+            "Container",
+            "Environment",
+            shell_words::quote(&if let Some(value) = value {
+                format!("{key}={value}")
+            } else {
+                key.clone()
+            }),
+        );
+    }
+
+    for env_file in &service.env_file {
+        unit.add("Container", "EnvironmentFile", env_file.to_string_lossy());
+    }
+
+    for port in &service.ports {
+        unit.add("Container", "PublishPort", port.to_string());
+    }
+
+    for expose in &service.expose {
+        unit.add("Container", "ExposeHostPort", expose);
+    }
+
+    for service_volume in &service.volumes {
+        match &service_volume.r#type {
+            ServiceVolumeType::Tmpfs => {
+                unit.add("Container", "Tmpfs", service_volume.to_string());
+            }
+            ServiceVolumeType::Volume(Some(source)) => {
+                unit.add(
+                    "Container",
+                    "Volume",
+                    ServiceVolume {
+                        r#type: ServiceVolumeType::Volume(Some(volume(file, source))),
+                        ..service_volume.clone()
+                    }
+                    .to_string(),
+                );
+            }
+            _ => {
+                unit.add("Container", "Volume", service_volume.to_string());
+            }
+        }
+    }
+
+    if let Some(network_mode) = &service.network_mode {
+        if let Some(service) = network_mode.strip_prefix("service:") {
+            let (_, container_name) = file.services[service]
+                .container_names(project_name, service)
+                .next()
+                .unwrap();
+
+            unit.add(
+                "Container",
+                "Network",
+                format!("container:{container_name}"),
+            );
+        } else {
+            unit.add("Container", "Network", network_mode);
+        }
+    }
+
+    for (name, service_network) in &service.networks {
+        let mut options = service_network
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+
+        if let Some(mac_address) = &service.mac_address {
+            if options.is_empty() {
+                options = format!(":mac={mac_address}");
+            } else {
+                options = format!("{options},mac={mac_address}");
+            }
+        }
+
+        unit.add(
+            "Container",
+            "Network",
+            format!("{}{options}", network(file, name)),
+        );
+    }
+
+    for secret in &service.secrets {
+        unit.add(
+            "Container",
+            "Secret",
+            FileReference {
+                source: file.secrets[&secret.source].name.clone().unwrap(),
+                ..secret.clone()
+            }
+            .to_string(),
+        );
+    }
+
+    for config in &service.configs {
+        unit.add("Container", "Secret", file.config_mount(config));
+    }
+
+    if let Some(healthcheck) = &service.healthcheck {
+        if healthcheck.disable.unwrap_or_default() {
+            unit.add("Container", "HealthCmd", "none");
+        } else {
+            if !healthcheck.test.is_empty() {
+                unit.add(
+                    "Container",
+                    "HealthCmd",
+                    serde_json::to_string(&healthcheck.test).unwrap(),
+                );
+            }
+
+            if let Some(interval) = healthcheck.interval {
+                unit.add(
+                    "Container",
+                    "HealthInterval",
+                    format!("{}s", interval.as_secs()),
+                );
+            }
+
+            if let Some(timeout) = healthcheck.timeout {
+                unit.add(
+                    "Container",
+                    "HealthTimeout",
+                    format!("{}s", timeout.as_secs()),
+                );
+            }
+
+            if let Some(start_period) = healthcheck.start_period {
+                unit.add(
+                    "Container",
+                    "HealthStartPeriod",
+                    format!("{}s", start_period.as_secs()),
+                );
+            }
+
+            if let Some(retries) = healthcheck.retries {
+                unit.add("Container", "HealthRetries", retries.to_string());
+            }
+        }
+    }
+
+    if let Some(hostname) = &service.hostname {
+        unit.add("Container", "HostName", hostname);
+    }
+
+    if let Some(user) = &service.user {
+        unit.add("Container", "User", user);
+    }
+
+    if let Some(working_dir) = &service.working_dir {
+        unit.add("Container", "WorkingDir", working_dir.to_string_lossy());
+    }
+
+    if !service.cap_add.is_empty() {
+        unit.add("Container", "AddCapability", service.cap_add.join(" "));
+    }
+
+    if !service.cap_drop.is_empty() {
+        unit.add("Container", "DropCapability", service.cap_drop.join(" "));
+    }
+
+    for device in &service.devices {
+        unit.add("Container", "AddDevice", device.to_string());
+    }
+
+    for dns in &service.dns {
+        unit.add("Container", "DNS", dns);
+    }
+
+    for dns_opt in &service.dns_opt {
+        unit.add("Container", "DNSOption", dns_opt);
+    }
+
+    for dns_search in &service.dns_search {
+        unit.add("Container", "DNSSearch", dns_search);
+    }
+
+    if service.init.unwrap_or_default() {
+        unit.add("Container", "RunInit", "true");
+    }
+
+    if service.read_only.unwrap_or_default() {
+        unit.add("Container", "ReadOnly", "true");
+    }
+
+    for (key, value) in &service.sysctls {
+        unit.add("Container", "Sysctl", format!("{key}={value}"));
+    }
+
+    for tmpfs in &service.tmpfs {
+        unit.add("Container", "Tmpfs", tmpfs.to_string_lossy());
+    }
+
+    for (key, value) in &service.ulimits {
+        unit.add("Container", "Ulimit", format!("{key}={value}"));
+    }
+
+    if let Some(userns_mode) = &service.userns_mode {
+        unit.add("Container", "UserNS", userns_mode);
+    }
+
+    if let Some(stop_grace_period) = service.stop_grace_period {
+        unit.add(
+            "Container",
+            "StopTimeout",
+            stop_grace_period.as_secs().to_string(),
+        );
+    }
+
+    // Options without a Quadlet key are passed to `podman run` as is
+    let (global_args, mut args) = Service {
+        cap_add: Vec::new(),
+        cap_drop: Vec::new(),
+        command: Vec::new(),
+        devices: IndexSet::new(),
+        dns: Vec::new(),
+        dns_opt: Vec::new(),
+        dns_search: Vec::new(),
+        entrypoint: Vec::new(),
+        env_file: Vec::new(),
+        environment: IndexMap::new(),
+        expose: Vec::new(),
+        healthcheck: None,
+        hostname: None,
+        image: None,
+        init: None,
+        labels: IndexMap::new(),
+        network_mode: None,
+        ports: Vec::new(),
+        read_only: None,
+        restart: None,
+        stop_grace_period: None,
+        sysctls: IndexMap::new(),
+        tmpfs: Vec::new(),
+        ulimits: IndexMap::new(),
+        user: None,
+        userns_mode: None,
+        working_dir: None,
+        ..service.clone()
+    }
+    .to_args();
+
+    if service.networks.contains_key("default") {
+        args.extend([String::from("--network-alias"), service_name.to_owned()]);
+    }
+
+    if !global_args.is_empty() {
+        unit.add("Container", "GlobalArgs", shell_words::join(global_args));
+    }
+
+    if !args.is_empty() {
+        unit.add("Container", "PodmanArgs", shell_words::join(args));
+    }
+
+    if let Some(restart) = &service.restart {
+        unit.add(
+            "Service",
+            "Restart",
+            match restart {
+                RestartPolicy::No => "no",
+                RestartPolicy::Always | RestartPolicy::UnlessStopped => "always",
+                RestartPolicy::OnFailure => "on-failure",
+            },
+        );
+    }
+
+    unit.add("Install", "WantedBy", "default.target");
+
+    unit
+}
+
+pub(crate) fn units(file: &Compose) -> (IndexMap<String, Unit>, Vec<String>) {
+    let project_name = file.name.as_ref().unwrap();
+    let mut units = IndexMap::new();
+    let mut warnings = Vec::new();
+
+    let mut pod = Unit::default();
+    pod.add("Pod", "PodName", project_name);
+    pod.add(
+        "Pod",
+        "PodmanArgs",
+        shell_words::join([
+            "--share",
+            "none",
+            "--label",
+            &format!("io.podman.compose.project={project_name}"),
+        ]),
+    );
+    units.insert(format!("{project_name}.pod"), pod);
+
+    for network in file.networks.values() {
+        if network.external.unwrap_or_default() {
+            continue;
+        }
+
+        let name = network.name.clone().unwrap();
+        let mut unit = Unit::default();
+        unit.add("Network", "NetworkName", &name);
+
+        for (key, value) in [
+            ("project", project_name.as_str()),
+            ("network", name.as_str()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (format!("io.podman.compose.{key}"), value))
+        .chain(
+            network
+                .labels
+                .iter()
+                .map(|(key, value)| (key.clone(), value.as_str())),
+        ) {
+            unit.add(
+                "Network",
+                "Label",
+                shell_words::quote(&format!("{key}={value}")),
+            );
+        }
+
+        if let Some(driver) = &network.driver {
+            unit.add("Network", "Driver", driver);
+        }
+
+        for (key, value) in &network.driver_opts {
+            unit.add("Network", "Options", format!("{key}={value}"));
+        }
+
+        if network.enable_ipv6.unwrap_or_default() {
+            unit.add("Network", "IPv6", "true");
+        }
+
+        if network.internal.unwrap_or_default() {
+            unit.add("Network", "Internal", "true");
+        }
+
+        if let Some(ipam) = &network.ipam {
+            if let Some(driver) = &ipam.driver {
+                unit.add("Network", "IPAMDriver", driver);
+            }
+
+            for config in &ipam.config {
+                if let Some(subnet) = &config.subnet {
+                    unit.add("Network", "Subnet", subnet);
+                }
+
+                if let Some(ip_range) = &config.ip_range {
+                    unit.add("Network", "IPRange", ip_range);
+                }
+
+                if let Some(gateway) = &config.gateway {
+                    unit.add("Network", "Gateway", gateway);
+                }
+            }
+        }
+
+        units.insert(format!("{name}.network"), unit);
+    }
+
+    for volume in file.volumes.values() {
+        if volume.external.unwrap_or_default() {
+            continue;
+        }
+
+        let name = volume.name.clone().unwrap();
+        let mut unit = Unit::default();
+        let mut args = Vec::new();
+        unit.add("Volume", "VolumeName", &name);
+
+        for (key, value) in [
+            ("project", project_name.as_str()),
+            ("volume", name.as_str()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (format!("io.podman.compose.{key}"), value))
+        .chain(
+            volume
+                .labels
+                .iter()
+                .map(|(key, value)| (key.clone(), value.as_str())),
+        ) {
+            unit.add(
+                "Volume",
+                "Label",
+                shell_words::quote(&format!("{key}={value}")),
+            );
+        }
+
+        if let Some(driver) = &volume.driver {
+            unit.add("Volume", "Driver", driver);
+        }
+
+        for (key, value) in &volume.driver_opts {
+            match key.as_str() {
+                "type" => unit.add("Volume", "Type", value),
+                "device" => unit.add("Volume", "Device", value),
+                "o" => unit.add("Volume", "Options", value),
+                _ => args.extend([String::from("--opt"), format!("{key}={value}")]),
+            }
+        }
+
+        if !args.is_empty() {
+            unit.add("Volume", "PodmanArgs", shell_words::join(args));
+        }
+
+        units.insert(format!("{name}.volume"), unit);
+    }
+
+    for (name, secret) in &file.secrets {
+        if !secret.external.unwrap_or_default() {
+            warnings.push(format!(
+                "Secret \"{name}\" must be created beforehand as \"{}\"",
+                secret.name.as_ref().unwrap()
+            ));
+        }
+    }
+
+    for (name, config) in &file.configs {
+        if !config.external.unwrap_or_default() {
+            warnings.push(format!(
+                "Config \"{name}\" must be created beforehand as secret \"{}\"",
+                config.name.as_ref().unwrap()
+            ));
+        }
+    }
+
+    for (service_name, service) in &file.services {
+        units.insert(
+            format!("{}.container", service_unit(project_name, service_name)),
+            container(file, service_name, service, &mut warnings),
+        );
+    }
+
+    (units, warnings)
+}
+
+pub(crate) fn run(args: Args, file: &Compose) -> Result<()> {
+    let (units, warnings) = units(file);
+
+    for warning in warnings {
+        eprintln!("{} {warning}", *STYLED_WARNING);
+    }
+
+    if let Some(output) = args.output {
+        fs::create_dir_all(&output)
+            .with_context(|| anyhow!("{} cannot be created", output.display()))?;
+
+        for (name, unit) in units {
+            fs::write(output.join(&name), unit.to_string())?;
+            eprintln!("Wrote {}", output.join(name).display());
+        }
+    } else {
+        for (i, (name, unit)) in units.into_iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+
+            println!("# {name}");
+            print!("{unit}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::Path};
+
+    use path_absolutize::Absolutize;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{compose, config::Config};
+
+    fn parse() -> Compose {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/quadlet/compose.yaml")],
+            project_directory: Path::new("tests/fixtures/quadlet")
+                .absolutize()
+                .unwrap()
+                .to_path_buf(),
+            ..Config::default()
+        };
+
+        compose::parse(&config, false, &[]).unwrap()
+    }
+
+    #[test]
+    fn quadlet() {
+        let file = parse();
+        let name = file.name.as_ref().unwrap();
+        let (units, warnings) = units(&file);
+
+        assert_eq!(
+            units.keys().collect::<Vec<_>>(),
+            [
+                &format!("{name}.pod"),
+                &format!("{name}_front.network"),
+                &format!("{name}_data.volume"),
+                &format!("{name}-web.container"),
+                &format!("{name}-db.container"),
+                &format!("{name}-cache.container"),
+            ]
+        );
+
+        let web = &units[&format!("{name}-web.container")];
+        assert_eq!(web.get("Unit", "Requires"), [format!("{name}-db.service")]);
+        assert_eq!(web.get("Unit", "Wants"), [format!("{name}-cache.service")]);
+        assert_eq!(
+            web.get("Unit", "After"),
+            [
+                format!("{name}-db.service"),
+                format!("{name}-cache.service")
+            ]
+        );
+        assert_eq!(web.get("Container", "Pod"), [format!("{name}.pod")]);
+        assert_eq!(
+            web.get("Container", "Environment"),
+            ["'GREETING=hello world'"]
+        );
+        assert_eq!(
+            web.get("Container", "Volume"),
+            [format!("{name}_data.volume:/usr/share/nginx/html:ro")]
+        );
+        assert_eq!(
+            web.get("Container", "Network"),
+            [format!("{name}_front.network")]
+        );
+        assert_eq!(
+            web.get("Container", "HealthCmd"),
+            [r#"["CMD","curl","-f","http://localhost"]"#]
+        );
+        assert_eq!(web.get("Container", "HealthInterval"), ["30s"]);
+        assert_eq!(web.get("Container", "PodmanArgs"), ["--privileged"]);
+        assert_eq!(web.get("Service", "Restart"), ["always"]);
+        assert_eq!(
+            web.get("Container", "Image"),
+            ["docker.io/library/nginx:alpine"]
+        );
+
+        let db = &units[&format!("{name}-db.container")];
+        assert_eq!(db.get("Container", "Secret"), [format!("{name}_password")]);
+        assert_eq!(
+            db.get("Container", "Exec"),
+            ["postgres -c 'log_statement=all'"]
+        );
+
+        let cache = &units[&format!("{name}-cache.container")];
+        assert_eq!(cache.get("Container", "Pull"), ["newer"]);
+
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings[0].starts_with("Secret \"password\""));
+        assert!(warnings[1].contains("becoming healthy"));
+        assert!(warnings[2].contains("one replica"));
+    }
+
+    #[test]
+    fn escape_specifiers() {
+        let mut unit = Unit::default();
+        unit.add("Container", "Environment", "DISCOUNT=50%");
+
+        assert_eq!(unit.get("Container", "Environment"), ["DISCOUNT=50%%"]);
+        assert_eq!(unit.to_string(), "[Container]\nEnvironment=DISCOUNT=50%%\n");
+    }
+
+    #[test]
+    fn output() {
+        let file = parse();
+        let output = env::temp_dir().join(format!("haddock-quadlet-{}", fastrand::u64(..)));
+
+        run(
+            Args {
+                output: Some(output.clone()),
+            },
+            &file,
+        )
+        .unwrap();
+
+        let (units, _) = units(&file);
+        let mut written = fs::read_dir(&output)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        written.sort();
+        let mut expected = units.keys().cloned().collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(written, expected);
+
+        for (name, unit) in &units {
+            assert_eq!(
+                fs::read_to_string(output.join(name)).unwrap(),
+                unit.to_string()
+            );
+        }
+
+        fs::remove_dir_all(&output).unwrap();
+    }
+}
@@ -3,10 +3,7 @@ use console::Style;
 use futures::{stream::select_all, TryStreamExt};
 use itertools::Itertools;
 
-use crate::{
-    compose::types::Compose,
-    podman::{types::Container, Podman},
-};
+use crate::{compose::types::Compose, podman::Podman};
 
 /// View output from containers
 #[derive(clap::Args, Debug)]
@@ -46,19 +43,12 @@ pub(crate) struct Args {
 pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<()> {
     let tail = args.tail.map(|tail| tail.to_string());
 
-    let output = podman
-        .force_run([
-            "ps",
-            "--all",
-            "--format",
-            "json",
-            "--filter",
+    let containers = podman
+        .containers([
             "label=io.podman.compose.oneoff=false",
-            "--filter",
             &format!("pod={}", file.name.as_ref().unwrap()),
         ])
-        .await?;
-    let containers = serde_json::from_str::<Vec<Container>>(&output)?
+        .await?
         .into_iter()
         .filter_map(|mut container| {
             container
@@ -5,7 +5,7 @@ use itertools::Itertools;
 use crate::{
     compose::types::Compose,
     config::Config,
-    podman::{types::Container, Podman},
+    podman::Podman,
     progress::{Finish, Progress},
 };
 
@@ -43,21 +43,13 @@ pub(crate) async fn run(
     file: &Compose,
     config: &Config,
 ) -> Result<()> {
-    let output = podman
-        .force_run([
-            "ps",
-            "--all",
-            "--format",
-            "json",
-            "--filter",
+    let containers = podman
+        .containers([
             "status=running",
-            "--filter",
             "label=io.podman.compose.oneoff=false",
-            "--filter",
             &format!("pod={}", file.name.as_ref().unwrap()),
         ])
-        .await?;
-    let containers = serde_json::from_str::<Vec<Container>>(&output)?
+        .await?
         .into_iter()
         .filter_map(|mut container| {
             container
@@ -3,10 +3,7 @@ use std::fmt::{self, Display, Formatter};
 use anyhow::{anyhow, bail, Result};
 use clap::ValueEnum;
 
-use crate::{
-    compose::types::Compose,
-    podman::{types::Container, Podman},
-};
+use crate::{compose::types::Compose, podman::Podman};
 
 /// Print the public port for a port binding
 #[derive(clap::Args, Debug)]
@@ -36,21 +33,13 @@ impl Display for Protocol {
 }
 
 pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<()> {
-    let output = podman
-        .force_run([
-            "ps",
-            "--all",
-            "--format",
-            "json",
-            "--filter",
+    let containers = podman
+        .containers([
             "label=io.podman.compose.oneoff=false",
-            "--filter",
             &format!("pod={}", file.name.as_ref().unwrap()),
-            "--filter",
             &format!("label=io.podman.compose.service={}", args.service),
         ])
         .await?;
-    let containers = serde_json::from_str::<Vec<Container>>(&output)?;
 
     if containers.is_empty() {
         bail!("No container found for service \"{}\"", args.service);
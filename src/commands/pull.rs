@@ -0,0 +1,168 @@
+use anyhow::Result;
+use futures::{stream::FuturesUnordered, TryStreamExt};
+use indexmap::IndexMap;
+use petgraph::{algo::has_path_connecting, graphmap::DiGraphMap};
+
+use crate::{
+    compose::types::{Compose, PullPolicy},
+    config::Config,
+    podman::{self, Podman},
+    progress::{Finish, Progress},
+};
+
+/// Pull service images
+#[derive(clap::Args, Debug)]
+#[command(next_display_order = None)]
+pub(crate) struct Args {
+    services: Vec<String>,
+
+    /// Pull what it can and ignores images with pull failures
+    #[arg(long)]
+    ignore_pull_failures: bool,
+
+    /// Also pull services declared as dependencies
+    #[arg(long)]
+    include_deps: bool,
+
+    /// Pull without printing progress information
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+pub(crate) async fn pull_images(
+    podman: &Podman,
+    progress: &Progress,
+    images: &IndexMap<String, PullPolicy>,
+    ignore_pull_failures: bool,
+    quiet: bool,
+) -> Result<()> {
+    images
+        .iter()
+        .map(|(image, pull_policy)| async move {
+            let spinner = progress.add_spinner(format!("Image {image}"), "Pulling");
+
+            let policy = match pull_policy {
+                PullPolicy::Never | PullPolicy::Build => {
+                    spinner.finish_with_message("Skipped");
+
+                    return Ok(());
+                }
+                PullPolicy::Missing => {
+                    if podman.exists("image", image).await? {
+                        spinner.finish_with_message("Exists");
+
+                        return Ok(());
+                    }
+
+                    podman::PullPolicy::Always
+                }
+                PullPolicy::Always => podman::PullPolicy::Always,
+                PullPolicy::Newer => podman::PullPolicy::Newer,
+            };
+
+            podman
+                .pull_image(image, policy, quiet)
+                .await
+                .finish_with_message(spinner, "Pulled")
+                .or_else(|err| {
+                    if ignore_pull_failures {
+                        Ok(())
+                    } else {
+                        Err(err)
+                    }
+                })
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect::<Vec<_>>()
+        .await
+        .map(|_| ())
+}
+
+pub(crate) async fn run(
+    args: Args,
+    podman: &Podman,
+    file: &Compose,
+    config: &Config,
+) -> Result<()> {
+    let mut dependencies = file
+        .services
+        .iter()
+        .flat_map(|(to, service)| service.depends_on.keys().map(move |from| (from, to, ())))
+        .collect::<DiGraphMap<_, _>>();
+
+    for service in file.services.keys() {
+        dependencies.add_node(service);
+    }
+
+    let mut images = IndexMap::new();
+
+    for (name, service) in &file.services {
+        if (args.services.is_empty()
+            || args.services.contains(name)
+            || (args.include_deps
+                && args
+                    .services
+                    .iter()
+                    .any(|service| has_path_connecting(&dependencies, name, service, None))))
+            && !(service.build.is_some() && service.image.is_none())
+        {
+            images
+                .entry(file.image_name(name))
+                .or_insert_with(|| service.pull_policy.clone().unwrap_or(PullPolicy::Always));
+        }
+    }
+
+    if !images.is_empty() {
+        let progress = if args.quiet {
+            Progress::hidden()
+        } else {
+            Progress::new(config)
+        };
+
+        pull_images(
+            podman,
+            &progress,
+            &images,
+            args.ignore_pull_failures,
+            args.quiet,
+        )
+        .await?;
+
+        progress.finish();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::podman::fake::{self, Operation};
+
+    fn pulls(engine: &fake::Fake, policy: podman::PullPolicy) -> Vec<String> {
+        let mut images = engine.targets(Operation::Pull(policy));
+        images.sort();
+
+        images
+    }
+
+    #[tokio::test]
+    async fn pull_policies() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/compose-pull/policies/docker-compose.yaml");
+        engine
+            .state_mut()
+            .images
+            .insert(String::from("alpine:3.19"), String::from("sha256:19"));
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        assert_eq!(
+            pulls(&engine, podman::PullPolicy::Always),
+            ["alpine:3.16", "alpine:3.18", "alpine:3.21"]
+        );
+        assert_eq!(pulls(&engine, podman::PullPolicy::Newer), ["alpine:3.17"]);
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{Error, Result};
+use futures::{stream::FuturesUnordered, TryStreamExt};
+use indexmap::IndexSet;
+use petgraph::{algo::has_path_connecting, graphmap::DiGraphMap};
+
+use crate::{
+    compose::types::Compose,
+    config::Config,
+    podman::Podman,
+    progress::{Finish, Progress},
+    utils::STYLED_WARNING,
+};
+
+/// Push service images
+#[derive(clap::Args, Debug)]
+#[command(next_display_order = None)]
+pub(crate) struct Args {
+    services: Vec<String>,
+
+    /// Push what it can and ignores images with push failures
+    #[arg(long)]
+    ignore_push_failures: bool,
+
+    /// Also push images of services declared as dependencies
+    #[arg(long)]
+    include_deps: bool,
+}
+
+/// Push images, returning the ones that failed when failures are ignored
+async fn push_images<'a>(
+    podman: &Podman,
+    progress: &Progress,
+    images: &IndexSet<&'a String>,
+    ignore_push_failures: bool,
+) -> Result<Vec<(&'a String, Error)>> {
+    images
+        .iter()
+        .map(|image| async move {
+            let spinner = progress.add_spinner(format!("Image {image}"), "Pushing");
+
+            match podman
+                .push_image(image)
+                .await
+                .finish_with_message(spinner, "Pushed")
+            {
+                Ok(()) => Ok(None),
+                Err(err) if ignore_push_failures => Ok(Some((*image, err))),
+                Err(err) => Err(err),
+            }
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_filter_map(|failure| async move { Ok(failure) })
+        .try_collect()
+        .await
+}
+
+pub(crate) async fn run(
+    args: Args,
+    podman: &Podman,
+    file: &Compose,
+    config: &Config,
+) -> Result<()> {
+    let mut dependencies = file
+        .services
+        .iter()
+        .flat_map(|(to, service)| service.depends_on.keys().map(move |from| (from, to, ())))
+        .collect::<DiGraphMap<_, _>>();
+
+    for service in file.services.keys() {
+        dependencies.add_node(service);
+    }
+
+    let images = file
+        .services
+        .iter()
+        .filter_map(|(name, service)| {
+            if args.services.is_empty()
+                || args.services.contains(name)
+                || (args.include_deps
+                    && args
+                        .services
+                        .iter()
+                        .any(|service| has_path_connecting(&dependencies, name, service, None)))
+            {
+                service.image.as_ref()
+            } else {
+                None
+            }
+        })
+        .collect::<IndexSet<_>>();
+
+    if !images.is_empty() {
+        let progress = Progress::new(config);
+
+        let failures = push_images(podman, &progress, &images, args.ignore_push_failures).await?;
+
+        progress.finish();
+
+        for (image, err) in failures {
+            eprintln!(
+                "{} Image \"{image}\" was not pushed: {err:#}",
+                *STYLED_WARNING
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::podman::fake::{self, Operation};
+
+    fn setup() -> (Config, Compose, fake::Fake, Podman) {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/push/compose.yaml");
+
+        for service in file.services.values() {
+            let image = service.image.clone().unwrap();
+            let id = format!("sha256:{image}");
+            engine.state_mut().images.insert(image, id);
+        }
+
+        (config, file, engine, podman)
+    }
+
+    fn pushes(engine: &fake::Fake) -> Vec<String> {
+        let mut images = engine.targets(Operation::Push);
+        images.sort();
+
+        images
+    }
+
+    #[tokio::test]
+    async fn push() {
+        let (config, file, engine, podman) = setup();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        assert_eq!(
+            pushes(&engine),
+            [
+                "registry.example.com/api",
+                "registry.example.com/cache",
+                "registry.example.com/db",
+                "registry.example.com/web",
+            ]
+        );
+
+        engine.clear_calls();
+        run(fake::args(&["web"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(pushes(&engine), ["registry.example.com/web"]);
+    }
+
+    #[tokio::test]
+    async fn include_deps() {
+        let (config, file, engine, podman) = setup();
+
+        run(
+            fake::args(&["--include-deps", "web"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            pushes(&engine),
+            [
+                "registry.example.com/api",
+                "registry.example.com/db",
+                "registry.example.com/web",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ignore_push_failures() {
+        let (config, file, engine, podman) = setup();
+        engine
+            .state_mut()
+            .images
+            .shift_remove("registry.example.com/db");
+
+        assert_eq!(
+            run(fake::args(&["db"]), &podman, &file, &config)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "registry.example.com/db: image not known"
+        );
+
+        engine.clear_calls();
+        run(
+            fake::args(&["--ignore-push-failures"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(pushes(&engine).len(), 4);
+    }
+}
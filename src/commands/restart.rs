@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use anyhow::Result;
 use futures::{stream::FuturesUnordered, TryStreamExt};
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
 use petgraph::{graphmap::DiGraphMap, Direction};
 use tokio::sync::{broadcast, Barrier};
@@ -10,7 +10,7 @@ use tokio::sync::{broadcast, Barrier};
 use crate::{
     compose::types::Compose,
     config::Config,
-    podman::{types::Container, Podman},
+    podman::Podman,
     progress::{Finish, Progress},
 };
 
@@ -18,11 +18,11 @@ use crate::{
 #[derive(clap::Args, Debug)]
 #[command(next_display_order = None)]
 pub(crate) struct Args {
-    services: Vec<String>,
+    pub(crate) services: Vec<String>,
 
     /// Specify a shutdown timeout in seconds [default: 10]
     #[arg(short, long)]
-    timeout: Option<u32>,
+    pub(crate) timeout: Option<u32>,
 }
 
 async fn restart_containers(
@@ -32,65 +32,87 @@ async fn restart_containers(
     containers: &HashMap<String, Vec<String>>,
     args: Args,
 ) -> Result<()> {
-    let dependencies = &file
+    let mut dependencies = file
         .services
         .iter()
-        .filter(|(service, _)| containers.keys().contains(service))
-        .flat_map(|(to, service)| {
-            service
-                .depends_on
-                .keys()
-                .filter(|service| containers.keys().contains(service))
-                .map(move |from| (from, to, ()))
-        })
+        .flat_map(|(to, service)| service.depends_on.keys().map(move |from| (from, to, ())))
         .collect::<DiGraphMap<_, _>>();
+
+    for service in file.services.keys().chain(containers.keys()) {
+        dependencies.add_node(service);
+    }
+
     let capacity = dependencies
         .nodes()
         .map(|service| {
             dependencies
                 .neighbors_directed(service, Direction::Incoming)
                 .count()
+                .max(
+                    dependencies
+                        .neighbors_directed(service, Direction::Outgoing)
+                        .count(),
+                )
         })
         .max()
         .unwrap_or_default()
         .max(1);
-    let txs = &containers
-        .keys()
+    let stopped_txs = &dependencies
+        .nodes()
         .map(|service| (service, broadcast::channel(capacity).0))
         .collect::<IndexMap<_, _>>();
-    let barrier = &Barrier::new(containers.values().map(Vec::len).sum());
+    let started_txs = &dependencies
+        .nodes()
+        .map(|service| (service, broadcast::channel(capacity).0))
+        .collect::<IndexMap<_, _>>();
+    let barrier = &Barrier::new(dependencies.node_count());
+    let dependencies = &dependencies;
+    let args = &args;
 
-    containers
-        .iter()
-        .map(|(service, containers)| async move {
-            containers
+    dependencies
+        .nodes()
+        .map(|service| async move {
+            let spinners = containers
+                .get(service)
+                .into_iter()
+                .flatten()
+                .map(|container| {
+                    (
+                        container,
+                        progress.add_spinner(format!("Container {container}"), "Restarting"),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let mut stopped_rx = stopped_txs[service].subscribe();
+            let mut started_rx = started_txs[service].subscribe();
+
+            barrier.wait().await;
+
+            // Dependents are stopped before the services they depend on, and started after them
+            for _ in dependencies.neighbors_directed(service, Direction::Outgoing) {
+                stopped_rx.recv().await?;
+            }
+
+            spinners
                 .iter()
-                .map(|container| async move {
-                    let spinner =
-                        progress.add_spinner(format!("Container {container}"), "Restarting");
-                    let mut rx = txs[service].subscribe();
+                .map(|(container, _)| podman.stop_container(container, args.timeout))
+                .collect::<FuturesUnordered<_>>()
+                .try_collect::<Vec<_>>()
+                .await?;
 
-                    barrier.wait().await;
+            for dependency in dependencies.neighbors_directed(service, Direction::Incoming) {
+                stopped_txs[dependency].send(())?;
+            }
 
-                    for _ in dependencies.neighbors_directed(service, Direction::Incoming) {
-                        rx.recv().await?;
-                    }
+            for _ in dependencies.neighbors_directed(service, Direction::Incoming) {
+                started_rx.recv().await?;
+            }
 
+            spinners
+                .into_iter()
+                .map(|(container, spinner)| async move {
                     podman
-                        .run(
-                            ["restart"]
-                                .into_iter()
-                                .chain(
-                                    if let Some(timeout) =
-                                        &args.timeout.map(|timeout| timeout.to_string())
-                                    {
-                                        vec!["--time", timeout]
-                                    } else {
-                                        vec![]
-                                    },
-                                )
-                                .chain([container.as_ref()]),
-                        )
+                        .start_container(container)
                         .await
                         .finish_with_message(spinner, "Restarted")
                 })
@@ -98,8 +120,8 @@ async fn restart_containers(
                 .try_collect::<Vec<_>>()
                 .await?;
 
-            for dependent in dependencies.neighbors(service) {
-                txs[dependent].send(())?;
+            for dependent in dependencies.neighbors_directed(service, Direction::Outgoing) {
+                started_txs[dependent].send(())?;
             }
 
             Ok(())
@@ -110,32 +132,48 @@ async fn restart_containers(
         .map(|_| ())
 }
 
+fn restarted_services(file: &Compose, services: &[String]) -> IndexSet<String> {
+    let mut restarted = services.iter().cloned().collect::<IndexSet<_>>();
+    let mut index = 0;
+
+    while let Some(service) = restarted.get_index(index).cloned() {
+        restarted.extend(
+            file.services
+                .iter()
+                .filter(|(_, dependent)| {
+                    dependent
+                        .depends_on
+                        .get(&service)
+                        .is_some_and(|dependency| dependency.restart.unwrap_or_default())
+                })
+                .map(|(name, _)| name.clone()),
+        );
+        index += 1;
+    }
+
+    restarted
+}
+
 pub(crate) async fn run(
     args: Args,
     podman: &Podman,
     file: &Compose,
     config: &Config,
 ) -> Result<()> {
-    let output = podman
-        .force_run([
-            "ps",
-            "--all",
-            "--format",
-            "json",
-            "--filter",
+    let services = restarted_services(file, &args.services);
+    let containers = podman
+        .containers([
             "label=io.podman.compose.oneoff=false",
-            "--filter",
             &format!("pod={}", file.name.as_ref().unwrap()),
         ])
-        .await?;
-    let containers = serde_json::from_str::<Vec<Container>>(&output)?
+        .await?
         .into_iter()
         .filter_map(|mut container| {
             container
                 .labels
                 .and_then(|labels| labels.service)
                 .and_then(|service| {
-                    if args.services.contains(&service)
+                    if services.contains(&service)
                         || (args.services.is_empty() && file.services.keys().contains(&service))
                     {
                         container.names.pop_front().map(|name| (service, name))
@@ -156,3 +194,100 @@ pub(crate) async fn run(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        commands::{create, start},
+        podman::fake::{self, Operation},
+    };
+
+    async fn setup() -> (Config, Compose, fake::Fake, Podman) {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/dependencies/restart.yaml");
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        start::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        engine.clear_calls();
+
+        (config, file, engine, podman)
+    }
+
+    #[test]
+    fn restarted() {
+        let (_, file) = fake::project("tests/fixtures/dependencies/restart.yaml");
+
+        assert_eq!(
+            restarted_services(&file, &[String::from("db")]),
+            IndexSet::from(["db", "api", "web"].map(String::from))
+        );
+        assert_eq!(
+            restarted_services(&file, &[String::from("api")]),
+            IndexSet::from(["api", "web"].map(String::from))
+        );
+        assert_eq!(
+            restarted_services(&file, &[String::from("worker")]),
+            IndexSet::from([String::from("worker")])
+        );
+    }
+
+    #[tokio::test]
+    async fn dependents() {
+        let (config, file, engine, podman) = setup().await;
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&["db"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        // Dependents are stopped first and started last
+        assert_eq!(
+            engine.targets(Operation::Stop),
+            [
+                format!("{name}_web_1"),
+                format!("{name}_api_1"),
+                format!("{name}_db_1")
+            ]
+        );
+        assert_eq!(
+            engine.targets(Operation::Start),
+            [
+                format!("{name}_db_1"),
+                format!("{name}_api_1"),
+                format!("{name}_web_1")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn all() {
+        let (config, file, engine, podman) = setup().await;
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        let stopped = engine.targets(Operation::Stop);
+        let started = engine.targets(Operation::Start);
+        let position = |containers: &[String], service: &str| {
+            containers
+                .iter()
+                .position(|container| *container == format!("{name}_{service}_1"))
+                .unwrap()
+        };
+
+        assert_eq!(stopped.len(), 4);
+        assert_eq!(started.len(), 4);
+
+        for (dependent, dependency) in [("web", "api"), ("api", "db"), ("worker", "db")] {
+            assert!(position(&stopped, dependent) < position(&stopped, dependency));
+            assert!(position(&started, dependency) < position(&started, dependent));
+        }
+    }
+}
@@ -11,7 +11,7 @@ use tokio::sync::{broadcast, Barrier};
 use crate::{
     compose::types::Compose,
     config::Config,
-    podman::{types::Container, Podman},
+    podman::Podman,
     progress::{Finish, Progress},
 };
 
@@ -86,17 +86,7 @@ pub(crate) async fn remove_containers(
                     }
 
                     podman
-                        .run(
-                            ["rm", "--depend", "--ignore"]
-                                .into_iter()
-                                .chain(if args.stop { vec!["--force"] } else { vec![] })
-                                .chain(if args.volumes {
-                                    vec!["--volumes"]
-                                } else {
-                                    vec![]
-                                })
-                                .chain([container.as_ref()]),
-                        )
+                        .remove_container(container, args.stop, args.volumes)
                         .await
                         .finish_with_message(spinner, "Removed")
                 })
@@ -122,19 +112,12 @@ pub(crate) async fn run(
     file: &Compose,
     config: &Config,
 ) -> Result<()> {
-    let output = podman
-        .force_run([
-            "ps",
-            "--all",
-            "--format",
-            "json",
-            "--filter",
+    let containers = podman
+        .containers([
             "label=io.podman.compose.oneoff=false",
-            "--filter",
             &format!("pod={}", file.name.as_ref().unwrap()),
         ])
-        .await?;
-    let containers = serde_json::from_str::<Vec<Container>>(&output)?
+        .await?
         .into_iter()
         .filter_map(|mut container| {
             container
@@ -6,10 +6,10 @@ use clap::crate_version;
 use fastrand::Rng;
 
 use crate::{
-    commands::{create, start},
+    commands::{build, create, start},
     compose::types::{
-        parse_port, parse_service_volume, Compose, FileReference, Port, Service, ServiceVolume,
-        ServiceVolumeType,
+        parse_port, parse_service_volume, Compose, FileReference, Port, PullPolicy, Service,
+        ServiceVolume, ServiceVolumeType,
     },
     config::Config,
     podman::Podman,
@@ -20,7 +20,7 @@ use crate::{
 #[derive(clap::Args, Debug)]
 #[command(next_display_order = None)]
 pub(crate) struct Args {
-    service: String,
+    pub(crate) service: String,
     command: String,
     args: Vec<String>,
 
@@ -104,18 +104,9 @@ async fn run_container(
             .keys()
             .filter_map(|service_name| {
                 file.services.get(service_name).map(|service| {
-                    (1..=service
-                        .deploy
-                        .as_ref()
-                        .and_then(|deploy| deploy.replicas)
-                        .or(service.scale)
-                        .unwrap_or(1))
-                        .map(move |i| {
-                            service
-                                .container_name
-                                .clone()
-                                .unwrap_or_else(|| format!("{project_name}_{service_name}_{i}"))
-                        })
+                    service
+                        .container_names(project_name, service_name)
+                        .map(|(_, container_name)| container_name)
                 })
             })
             .flatten()
@@ -131,7 +122,11 @@ async fn run_container(
     .into_iter()
     .map(|label| format!("io.podman.compose.{}={}", label.0, label.1))
     .collect::<Vec<_>>();
-    let pull_policy = service.pull_policy.as_ref().map(ToString::to_string);
+    let pull_policy = service
+        .pull_policy
+        .as_ref()
+        .filter(|pull_policy| **pull_policy != PullPolicy::Build)
+        .map(ToString::to_string);
 
     let networks = service
         .networks
@@ -186,6 +181,11 @@ async fn run_container(
             .to_string()
         })
         .collect::<Vec<_>>();
+    let configs = service
+        .configs
+        .iter()
+        .map(|config| file.config_mount(config))
+        .collect::<Vec<_>>();
 
     let (global_args, service_args) = service.to_args();
 
@@ -221,6 +221,7 @@ async fn run_container(
                 })
                 .chain(volumes.iter().map(AsRef::as_ref))
                 .chain(secrets.iter().flat_map(|secret| ["--secret", secret]))
+                .chain(configs.iter().flat_map(|config| ["--secret", config]))
                 .chain(if args.detach {
                     vec!["--detach"]
                 } else {
@@ -250,21 +251,59 @@ pub(crate) async fn run(
             create::Args {
                 services: services.clone(),
                 pull: None,
+                build: false,
+                no_build: false,
                 force_recreate: false,
                 no_recreate: false,
+                no_deps: false,
+                always_recreate_deps: false,
+                renew_anon_volumes: false,
                 remove_orphans: args.remove_orphans,
+                scale: Vec::new(),
+            },
+            podman,
+            file,
+            config,
+        )
+        .await?;
+
+        start::run(
+            start::Args {
+                services,
+                wait_timeout: None,
+                no_deps: false,
             },
             podman,
             file,
             config,
         )
         .await?;
+    }
 
-        start::run(start::Args { services }, podman, file, config).await?;
+    let image = file.image_name(&args.service);
+
+    if service.build.is_some()
+        && (service.pull_policy == Some(PullPolicy::Build)
+            || !podman.exists("image", &image).await?)
+    {
+        build::run(
+            build::Args {
+                services: vec![args.service.clone()],
+                build_arg: Vec::new(),
+                no_cache: false,
+                pull: false,
+            },
+            podman,
+            file,
+            config,
+        )
+        .await?;
     }
 
     let mut service = service.clone();
 
+    service.image = Some(image);
+
     service.command = vec![args.command.clone()];
     service.command.extend(args.args.clone());
 
@@ -0,0 +1,125 @@
+use anyhow::Result;
+
+use crate::{
+    commands::{create, start},
+    compose::types::Compose,
+    config::Config,
+    podman::Podman,
+    utils::parse_key_val,
+};
+
+/// Scale services
+#[derive(clap::Args, Debug)]
+#[command(next_display_order = None)]
+pub(crate) struct Args {
+    #[arg(required = true, value_name = "SERVICE=REPLICAS", value_parser = parse_key_val::<String, u32>)]
+    services: Vec<(String, u32)>,
+
+    /// Don't start linked services
+    #[arg(long)]
+    no_deps: bool,
+}
+
+pub(crate) async fn run(
+    args: Args,
+    podman: &Podman,
+    file: &Compose,
+    config: &Config,
+) -> Result<()> {
+    let mut file = file.clone();
+    file.scale(&args.services)?;
+    let file = &file;
+
+    let services = args
+        .services
+        .into_iter()
+        .map(|(service, _)| service)
+        .collect::<Vec<_>>();
+
+    create::run(
+        create::Args {
+            services: services.clone(),
+            pull: None,
+            build: false,
+            no_build: false,
+            force_recreate: false,
+            no_recreate: true,
+            no_deps: args.no_deps,
+            always_recreate_deps: false,
+            renew_anon_volumes: false,
+            remove_orphans: false,
+            scale: Vec::new(),
+        },
+        podman,
+        file,
+        config,
+    )
+    .await?;
+
+    start::run(
+        start::Args {
+            services,
+            wait_timeout: None,
+            no_deps: args.no_deps,
+        },
+        podman,
+        file,
+        config,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::podman::fake::{self, Operation};
+
+    #[tokio::test]
+    async fn scale() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/scale/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        start::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        engine.clear_calls();
+
+        run(fake::args(&["web=3", "worker=1"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        let mut removed = engine.targets(Operation::Remove { volumes: false });
+        removed.sort();
+        assert_eq!(
+            removed,
+            [format!("{name}_worker_2"), format!("{name}_worker_3")]
+        );
+        assert_eq!(engine.targets(Operation::Create), [format!("{name}_web_3")]);
+        let mut stopped = engine.targets(Operation::Stop);
+        stopped.sort();
+        assert_eq!(stopped, removed);
+        let mut running = engine
+            .state()
+            .containers
+            .iter()
+            .filter(|(_, container)| container.state == "running")
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        running.sort();
+        assert_eq!(
+            running,
+            [
+                format!("{name}_web_1"),
+                format!("{name}_web_2"),
+                format!("{name}_web_3"),
+                format!("{name}_worker_1"),
+                String::from("database"),
+            ]
+        );
+    }
+}
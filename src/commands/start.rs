@@ -1,11 +1,17 @@
-use anyhow::Result;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
 use futures::{stream::FuturesUnordered, TryStreamExt};
 use indexmap::{IndexMap, IndexSet};
+use itertools::Itertools;
 use petgraph::{algo::has_path_connecting, graphmap::DiGraphMap, Direction};
-use tokio::sync::{broadcast, Barrier};
+use tokio::{
+    sync::{broadcast, Barrier},
+    time,
+};
 
 use crate::{
-    compose::types::Compose,
+    compose::types::{Compose, Condition, Service},
     config::Config,
     podman::Podman,
     progress::{Finish, Progress},
@@ -16,6 +22,107 @@ use crate::{
 #[command(next_display_order = None)]
 pub(crate) struct Args {
     pub(crate) services: Vec<String>,
+
+    /// Maximum duration in seconds to wait for dependencies to be healthy or completed
+    #[arg(long)]
+    pub(crate) wait_timeout: Option<u64>,
+
+    #[arg(skip)]
+    pub(crate) no_deps: bool,
+}
+
+pub(crate) async fn wait_healthy(
+    podman: &Podman,
+    service: &Service,
+    container: &str,
+) -> Result<()> {
+    if service
+        .healthcheck
+        .as_ref()
+        .is_some_and(|healthcheck| healthcheck.disable.unwrap_or_default())
+    {
+        bail!("Container \"{container}\" does not have a healthcheck");
+    }
+
+    if podman.dry_run() {
+        return Ok(());
+    }
+
+    loop {
+        let state = podman.inspect_container(container).await?;
+
+        if matches!(state.status.as_str(), "exited" | "stopped" | "dead") {
+            bail!(
+                "Container \"{container}\" exited with code {} before becoming healthy",
+                state.exit_code
+            );
+        }
+
+        match state.health.map(|health| health.status).as_deref() {
+            None | Some("") => bail!("Container \"{container}\" does not have a healthcheck"),
+            Some("healthy") => return Ok(()),
+            Some("unhealthy") => bail!("Container \"{container}\" is unhealthy"),
+            Some(_) => {}
+        }
+
+        // Healthchecks only run on a timer when Podman can use systemd
+        podman.run(["healthcheck", "run", container]).await.ok();
+
+        time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+pub(crate) async fn wait_completed(podman: &Podman, container: &str) -> Result<()> {
+    match podman.wait_container(container).await? {
+        0 => Ok(()),
+        code => bail!("Container \"{container}\" exited with code {code}"),
+    }
+}
+
+async fn wait_condition(
+    podman: &Podman,
+    progress: &Progress,
+    service: &Service,
+    containers: &[String],
+    condition: &Condition,
+    timeout: Option<u64>,
+) -> Result<()> {
+    if *condition == Condition::Started {
+        return Ok(());
+    }
+
+    containers
+        .iter()
+        .map(|container| async move {
+            let spinner = progress.add_spinner(format!("Container {container}"), "Waiting");
+            let wait = async {
+                match condition {
+                    Condition::Healthy => wait_healthy(podman, service, container).await,
+                    _ => wait_completed(podman, container).await,
+                }
+            };
+
+            if let Some(timeout) = timeout {
+                time::timeout(Duration::from_secs(timeout), wait)
+                    .await
+                    .unwrap_or_else(|_| {
+                        bail!("Container \"{container}\" did not become ready in {timeout}s")
+                    })
+            } else {
+                wait.await
+            }
+            .finish_with_message(
+                spinner,
+                match condition {
+                    Condition::Healthy => "Healthy",
+                    _ => "Exited",
+                },
+            )
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect::<Vec<_>>()
+        .await
+        .map(|_| ())
 }
 
 async fn start_containers(
@@ -38,9 +145,13 @@ async fn start_containers(
         for node in dependencies
             .nodes()
             .filter(|node| {
-                args.services
-                    .iter()
-                    .all(|service| !has_path_connecting(&dependencies, node, service, None))
+                if args.no_deps {
+                    !args.services.contains(node)
+                } else {
+                    args.services
+                        .iter()
+                        .all(|service| !has_path_connecting(&dependencies, node, service, None))
+                }
             })
             .collect::<Vec<_>>()
         {
@@ -67,14 +178,7 @@ async fn start_containers(
             .iter()
             .filter_map(|(name, service)| {
                 if dependencies.contains_node(name) {
-                    Some(
-                        service
-                            .deploy
-                            .as_ref()
-                            .and_then(|deploy| deploy.replicas)
-                            .or(service.scale)
-                            .unwrap_or(1) as usize,
-                    )
+                    Some(service.replicas() as usize)
                 } else {
                     None
                 }
@@ -82,23 +186,16 @@ async fn start_containers(
             .sum(),
     );
     let dependencies = &dependencies;
+    let args = &args;
 
     file.services
         .iter()
         .filter_map(|(service_name, service)| {
             if dependencies.contains_node(service_name) {
                 Some(async move {
-                    (1..=service
-                        .deploy
-                        .as_ref()
-                        .and_then(|deploy| deploy.replicas)
-                        .or(service.scale)
-                        .unwrap_or(1))
-                        .map(|i| async move {
-                            let container_name =
-                                service.container_name.clone().unwrap_or_else(|| {
-                                    format!("{}_{service_name}_{i}", file.name.as_ref().unwrap())
-                                });
+                    let container_names = service
+                        .container_names(file.name.as_ref().unwrap(), service_name)
+                        .map(|(_, container_name)| async move {
                             let spinner = progress
                                 .add_spinner(format!("Container {container_name}"), "Starting");
                             let mut rx = txs[service_name].subscribe();
@@ -112,17 +209,47 @@ async fn start_containers(
                             }
 
                             podman
-                                .run(["start", &container_name])
+                                .start_container(&container_name)
                                 .await
-                                .finish_with_message(spinner, "Started")
+                                .finish_with_message(spinner, "Started")?;
+
+                            anyhow::Ok(container_name)
                         })
                         .collect::<FuturesUnordered<_>>()
                         .try_collect::<Vec<_>>()
                         .await?;
+                    let container_names = &container_names;
 
-                    for dependent in dependencies.neighbors(service_name) {
-                        txs[dependent].send(())?;
-                    }
+                    dependencies
+                        .neighbors(service_name)
+                        .map(|dependent| {
+                            (
+                                &file.services[dependent].depends_on[service_name].condition,
+                                dependent,
+                            )
+                        })
+                        .into_group_map()
+                        .into_iter()
+                        .map(|(condition, dependents)| async move {
+                            wait_condition(
+                                podman,
+                                progress,
+                                service,
+                                container_names,
+                                condition,
+                                args.wait_timeout,
+                            )
+                            .await?;
+
+                            for dependent in dependents {
+                                txs[dependent].send(())?;
+                            }
+
+                            anyhow::Ok(())
+                        })
+                        .collect::<FuturesUnordered<_>>()
+                        .try_collect::<Vec<_>>()
+                        .await?;
 
                     Ok(())
                 })
@@ -158,3 +285,193 @@ pub(crate) async fn run(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        commands::create,
+        podman::fake::{self, Call, Operation},
+    };
+
+    #[tokio::test]
+    async fn dependencies() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        run(fake::args(&["app"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::Start),
+            [format!("{name}_dependency_1"), format!("{name}_app_1")]
+        );
+        assert!(engine
+            .state()
+            .containers
+            .values()
+            .all(|container| container.state == "running"));
+    }
+
+    #[tokio::test]
+    async fn no_deps() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        run(
+            Args {
+                no_deps: true,
+                ..fake::args(&["app"])
+            },
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(engine.targets(Operation::Start), [format!("{name}_app_1")]);
+    }
+
+    #[tokio::test]
+    async fn completed_successfully() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/dependencies/deps-completed-successfully.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        engine.clear_calls();
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        assert_eq!(
+            engine.state().calls,
+            [
+                Call {
+                    operation: Operation::Start,
+                    target: format!("{name}_oneshot_1"),
+                },
+                Call {
+                    operation: Operation::Wait,
+                    target: format!("{name}_oneshot_1"),
+                },
+                Call {
+                    operation: Operation::Start,
+                    target: format!("{name}_longrunning_1"),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_containers() {
+        let (config, file, _, podman) = fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+
+        assert!(run(fake::args(&[]), &podman, &file, &config).await.is_err());
+    }
+
+    fn set_health(engine: &fake::Fake, container: &str, health: &str) {
+        engine.state_mut().containers[container].health = Some(String::from(health));
+    }
+
+    #[tokio::test]
+    async fn healthy() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/start-fail/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        set_health(&engine, &format!("{name}_fail_1"), "healthy");
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::Start),
+            [format!("{name}_fail_1"), format!("{name}_depends_1")]
+        );
+    }
+
+    #[tokio::test]
+    async fn unhealthy() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/start-fail/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        set_health(&engine, &format!("{name}_fail_1"), "unhealthy");
+        let err = run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!("Container \"{name}_fail_1\" is unhealthy")
+        );
+        assert_eq!(engine.targets(Operation::Start), [format!("{name}_fail_1")]);
+    }
+
+    #[tokio::test]
+    async fn exited_before_healthy() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/start-fail/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+        let container = format!("{name}_fail_1");
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        {
+            let mut state = engine.state_mut();
+            let container = &mut state.containers[&container];
+            container.state = String::from("exited");
+            container.exit_code = 1;
+        }
+        let err = wait_healthy(&podman, &file.services["fail"], &container)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!("Container \"{container}\" exited with code 1 before becoming healthy")
+        );
+    }
+
+    #[tokio::test]
+    async fn health_timeout() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/start-fail/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        let err = run(
+            fake::args(&["--wait-timeout", "1"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!("Container \"{name}_fail_1\" did not become ready in 1s")
+        );
+        assert!(engine
+            .targets(Operation::Run)
+            .contains(&format!("healthcheck run {name}_fail_1")));
+        assert_eq!(engine.targets(Operation::Start), [format!("{name}_fail_1")]);
+    }
+}
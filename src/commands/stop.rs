@@ -10,7 +10,7 @@ use tokio::sync::{broadcast, Barrier};
 use crate::{
     compose::types::Compose,
     config::Config,
-    podman::{types::Container, Podman},
+    podman::Podman,
     progress::{Finish, Progress},
 };
 
@@ -32,65 +32,62 @@ pub(crate) async fn stop_containers(
     containers: &HashMap<String, Vec<String>>,
     args: Args,
 ) -> Result<()> {
-    let dependencies = &file
+    let mut dependencies = file
         .services
         .iter()
-        .filter(|(service, _)| containers.keys().contains(service))
-        .flat_map(|(from, service)| {
-            service
-                .depends_on
-                .keys()
-                .filter(|service| containers.keys().contains(service))
-                .map(move |to| (from, to, ()))
-        })
+        .flat_map(|(to, service)| service.depends_on.keys().map(move |from| (from, to, ())))
         .collect::<DiGraphMap<_, _>>();
+
+    for service in file.services.keys().chain(containers.keys()) {
+        dependencies.add_node(service);
+    }
+
     let capacity = dependencies
         .nodes()
         .map(|service| {
             dependencies
-                .neighbors_directed(service, Direction::Incoming)
+                .neighbors_directed(service, Direction::Outgoing)
                 .count()
         })
         .max()
         .unwrap_or_default()
         .max(1);
-    let txs = &containers
-        .keys()
+    let txs = &dependencies
+        .nodes()
         .map(|service| (service, broadcast::channel(capacity).0))
         .collect::<IndexMap<_, _>>();
-    let barrier = &Barrier::new(containers.values().map(Vec::len).sum());
+    let barrier = &Barrier::new(dependencies.node_count());
+    let dependencies = &dependencies;
+    let args = &args;
 
-    containers
-        .iter()
-        .map(|(service, containers)| async move {
-            containers
-                .iter()
-                .map(|container| async move {
-                    let spinner =
-                        progress.add_spinner(format!("Container {container}"), "Stopping");
-                    let mut rx = txs[service].subscribe();
+    dependencies
+        .nodes()
+        .map(|service| async move {
+            let spinners = containers
+                .get(service)
+                .into_iter()
+                .flatten()
+                .map(|container| {
+                    (
+                        container,
+                        progress.add_spinner(format!("Container {container}"), "Stopping"),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let mut rx = txs[service].subscribe();
 
-                    barrier.wait().await;
+            barrier.wait().await;
 
-                    for _ in dependencies.neighbors_directed(service, Direction::Incoming) {
-                        rx.recv().await?;
-                    }
+            // Dependents are stopped before the services they depend on
+            for _ in dependencies.neighbors_directed(service, Direction::Outgoing) {
+                rx.recv().await?;
+            }
 
+            spinners
+                .into_iter()
+                .map(|(container, spinner)| async move {
                     podman
-                        .run(
-                            ["stop"]
-                                .into_iter()
-                                .chain(
-                                    if let Some(timeout) =
-                                        &args.timeout.map(|timeout| timeout.to_string())
-                                    {
-                                        vec!["--time", timeout]
-                                    } else {
-                                        vec![]
-                                    },
-                                )
-                                .chain([container.as_ref()]),
-                        )
+                        .stop_container(container, args.timeout)
                         .await
                         .finish_with_message(spinner, "Stopped")
                 })
@@ -98,8 +95,8 @@ pub(crate) async fn stop_containers(
                 .try_collect::<Vec<_>>()
                 .await?;
 
-            for dependent in dependencies.neighbors(service) {
-                txs[dependent].send(())?;
+            for dependency in dependencies.neighbors_directed(service, Direction::Incoming) {
+                txs[dependency].send(())?;
             }
 
             Ok(())
@@ -116,19 +113,12 @@ pub(crate) async fn run(
     file: &Compose,
     config: &Config,
 ) -> Result<()> {
-    let output = podman
-        .force_run([
-            "ps",
-            "--all",
-            "--format",
-            "json",
-            "--filter",
+    let containers = podman
+        .containers([
             "label=io.podman.compose.oneoff=false",
-            "--filter",
             &format!("pod={}", file.name.as_ref().unwrap()),
         ])
-        .await?;
-    let containers = serde_json::from_str::<Vec<Container>>(&output)?
+        .await?
         .into_iter()
         .filter_map(|mut container| {
             container
@@ -156,3 +146,89 @@ pub(crate) async fn run(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        commands::{create, start},
+        podman::fake::{self, Operation},
+    };
+
+    async fn setup() -> (Config, Compose, fake::Fake, Podman) {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/dependencies/chain.yaml");
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        start::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        engine.clear_calls();
+
+        (config, file, engine, podman)
+    }
+
+    #[tokio::test]
+    async fn dependents_first() {
+        let (config, file, engine, podman) = setup().await;
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        let stopped = engine.targets(Operation::Stop);
+        let position = |service: &str| {
+            stopped
+                .iter()
+                .position(|container| *container == format!("{name}_{service}_1"))
+                .unwrap()
+        };
+        assert_eq!(stopped.len(), 4);
+        assert!(position("web") < position("api"));
+        assert!(position("api") < position("db"));
+    }
+
+    #[tokio::test]
+    async fn subset() {
+        let (config, file, engine, podman) = setup().await;
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&["db", "api"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::Stop),
+            [format!("{name}_api_1"), format!("{name}_db_1")]
+        );
+        assert_eq!(
+            engine.state().containers[&format!("{name}_web_1")].state,
+            "running"
+        );
+    }
+
+    #[tokio::test]
+    async fn orphans() {
+        let (config, mut file, engine, podman) = setup().await;
+        let name = file.name.clone().unwrap();
+        file.services.shift_remove("web");
+
+        run(fake::args(&[]), &podman, &file, &config).await.unwrap();
+
+        assert_eq!(
+            engine.state().containers[&format!("{name}_web_1")].state,
+            "running"
+        );
+
+        engine.clear_calls();
+        run(fake::args(&["web", "api"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        let mut stopped = engine.targets(Operation::Stop);
+        stopped.sort();
+        assert_eq!(stopped, [format!("{name}_api_1"), format!("{name}_web_1")]);
+    }
+}
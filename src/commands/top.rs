@@ -1,10 +1,7 @@
 use anyhow::Result;
 use futures::{stream::FuturesUnordered, TryFutureExt, TryStreamExt};
 
-use crate::{
-    compose::types::Compose,
-    podman::{types::Container, Podman},
-};
+use crate::{compose::types::Compose, podman::Podman};
 
 /// Display the running processes
 #[derive(clap::Args, Debug)]
@@ -14,19 +11,12 @@ pub(crate) struct Args {
 }
 
 pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<()> {
-    let output = podman
-        .force_run([
-            "ps",
-            "--all",
-            "--format",
-            "json",
-            "--filter",
+    let containers = podman
+        .containers([
             "status=running",
-            "--filter",
             &format!("pod={}", file.name.as_ref().unwrap()),
         ])
-        .await?;
-    let containers = serde_json::from_str::<Vec<Container>>(&output)?
+        .await?
         .into_iter()
         .filter_map(|mut container| {
             container
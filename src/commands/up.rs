@@ -1,9 +1,13 @@
-use std::process;
+use std::{
+    future, process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use futures::{stream::FuturesUnordered, TryStreamExt};
+use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
-use tokio::{select, signal};
+use tokio::{select, signal, time};
 
 use crate::{
     commands::{
@@ -12,24 +16,37 @@ use crate::{
     },
     compose::types::Compose,
     config::Config,
-    podman::{types::Container, Podman},
+    podman::{types::Event, Podman},
     progress::{Finish, Progress},
+    utils::parse_key_val,
 };
 
 /// Create and start containers
 #[derive(clap::Args, Debug)]
 #[command(next_display_order = None)]
 pub(crate) struct Args {
-    services: Vec<String>,
+    pub(crate) services: Vec<String>,
 
     /// Detached mode: Run containers in the background
-    #[arg(short, long, conflicts_with_all = ["attach", "attach_dependencies"])]
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["attach", "attach_dependencies", "abort_on_container_exit", "abort_on_container_failure", "exit_code_from"]
+    )]
     detach: bool,
 
     /// Pull image before running
     #[arg(long, value_enum)]
     pull: Option<PullPolicy>,
 
+    /// Build images before starting containers
+    #[arg(long, conflicts_with = "no_build")]
+    build: bool,
+
+    /// Don't build an image, even if it's missing
+    #[arg(long)]
+    no_build: bool,
+
     /// Remove containers for services not defined in the Compose file
     #[arg(long)]
     remove_orphans: bool,
@@ -50,6 +67,22 @@ pub(crate) struct Args {
     #[arg(long, conflicts_with = "force_recreate")]
     no_recreate: bool,
 
+    /// Don't start linked services
+    #[arg(long)]
+    no_deps: bool,
+
+    /// Recreate dependent containers
+    #[arg(long, conflicts_with = "no_recreate")]
+    always_recreate_deps: bool,
+
+    /// Remove anonymous volumes of recreated containers instead of leaving them behind
+    #[arg(short = 'V', long)]
+    renew_anon_volumes: bool,
+
+    /// Scale SERVICE to NUM instances, overrides the `scale` setting in the Compose file if present
+    #[arg(long, value_name = "SERVICE=NUM", value_parser = parse_key_val::<String, u32>)]
+    scale: Vec<(String, u32)>,
+
     /// Don't start the services after creating them
     #[arg(long)]
     no_start: bool,
@@ -74,30 +107,133 @@ pub(crate) struct Args {
     #[arg(long)]
     no_attach: Vec<String>,
 
+    /// Stops all containers if any container was stopped
+    #[arg(long, conflicts_with = "abort_on_container_failure")]
+    abort_on_container_exit: bool,
+
+    /// Stops all containers if any container exited with failure
+    #[arg(long)]
+    abort_on_container_failure: bool,
+
+    /// Return the exit code of the selected service container, implies --abort-on-container-exit
+    #[arg(long, conflicts_with = "abort_on_container_failure")]
+    exit_code_from: Option<String>,
+
     /// Wait for services to be running|healthy, implies detached mode
-    #[arg(long, conflicts_with_all = ["attach", "attach_dependencies"])]
+    #[arg(
+        long,
+        conflicts_with_all = ["attach", "attach_dependencies", "abort_on_container_exit", "abort_on_container_failure", "exit_code_from"]
+    )]
     wait: bool,
+
+    /// Maximum duration in seconds to wait for services or their dependencies to become ready
+    #[arg(long)]
+    wait_timeout: Option<u64>,
 }
 
-async fn wait_containers(
+async fn wait_containers<'a>(
     podman: &Podman,
     progress: &Progress,
-    containers: &[String],
-) -> Result<()> {
-    containers
+    file: &Compose,
+    containers: &'a IndexMap<String, String>,
+    timeout: Option<u64>,
+) -> Result<IndexSet<&'a String>> {
+    let pending = containers
         .iter()
-        .map(|container| async move {
+        .map(|(container, service)| async move {
             let spinner = progress.add_spinner(format!("Container {container}"), "Waiting");
+            let wait = async {
+                if podman.dry_run() {
+                    return Ok("Running");
+                }
+
+                loop {
+                    let state = podman.inspect_container(container).await?;
+
+                    match state.status.as_str() {
+                        // One-shot services are ready once they have exited successfully
+                        "exited" | "stopped" | "dead" => {
+                            return start::wait_completed(podman, container)
+                                .await
+                                .map(|()| "Exited");
+                        }
+                        "running" => {
+                            return if state.health.is_some_and(|health| !health.status.is_empty()) {
+                                start::wait_healthy(podman, &file.services[service], container)
+                                    .await
+                                    .map(|()| "Healthy")
+                            } else {
+                                Ok("Running")
+                            };
+                        }
+                        "initialized" | "stopping" => time::sleep(Duration::from_secs(1)).await,
+                        // Containers that were started but are not running will not become ready
+                        status => bail!("Container \"{container}\" is {status} instead of running"),
+                    }
+                }
+            };
 
-            podman
-                .run(["wait", "--condition", "running", container])
-                .await
-                .finish_with_message(spinner, "Running")
+            let result = if let Some(timeout) = timeout {
+                if let Ok(result) = time::timeout(Duration::from_secs(timeout), wait).await {
+                    result
+                } else {
+                    spinner.finish_with_message("Timed out");
+
+                    return Ok(Some(service));
+                }
+            } else {
+                wait.await
+            };
+            let message = result.as_ref().map_or("Error", |message| *message);
+
+            result.finish_with_message(spinner, message).map(|_| None)
         })
         .collect::<FuturesUnordered<_>>()
         .try_collect::<Vec<_>>()
-        .await
-        .map(|_| ())
+        .await?;
+
+    Ok(pending.into_iter().flatten().collect())
+}
+
+async fn wait_exit(
+    podman: &Podman,
+    containers: &IndexMap<String, String>,
+    since: u64,
+    abort_on_container_failure: bool,
+) -> Result<Option<(String, i32)>> {
+    let mut output = podman.watch([
+        "events",
+        "--format",
+        "json",
+        "--since",
+        &since.to_string(),
+        "--filter",
+        "event=died",
+    ])?;
+
+    while let Some(line) = output.try_next().await? {
+        let Ok(event) = serde_json::from_str::<Event>(&line) else {
+            continue;
+        };
+
+        if event.status != "died" {
+            continue;
+        }
+
+        if let Some(service) = containers.get(&event.name) {
+            let code = event.container_exit_code.unwrap_or_default();
+
+            if abort_on_container_failure && code == 0 {
+                continue;
+            }
+
+            eprintln!("{} exited with code {code}", event.name);
+
+            return Ok(Some((service.clone(), code)));
+        }
+    }
+
+    Ok(None)
 }
 
 pub(crate) async fn run(
@@ -106,13 +242,30 @@ pub(crate) async fn run(
     file: &Compose,
     config: &Config,
 ) -> Result<()> {
+    if let Some(exit_code_from) = &args.exit_code_from {
+        if !file.services.contains_key(exit_code_from) {
+            bail!("No such service: \"{exit_code_from}\"");
+        }
+    }
+
+    let mut file = file.clone();
+    file.scale(&args.scale)?;
+    let file = &file;
+
     create::run(
         create::Args {
             services: args.services.clone(),
             pull: args.pull,
+            build: args.build,
+            no_build: args.no_build,
             force_recreate: args.force_recreate,
             no_recreate: args.no_recreate,
+            no_deps: args.no_deps,
+            always_recreate_deps: args.always_recreate_deps,
+            renew_anon_volumes: args.renew_anon_volumes,
             remove_orphans: args.remove_orphans,
+            // Already applied to `file`
+            scale: Vec::new(),
         },
         podman,
         file,
@@ -121,9 +274,13 @@ pub(crate) async fn run(
     .await?;
 
     if !args.no_start {
+        let since = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
         start::run(
             start::Args {
                 services: args.services.clone(),
+                wait_timeout: args.wait_timeout,
+                no_deps: args.no_deps,
             },
             podman,
             file,
@@ -132,19 +289,12 @@ pub(crate) async fn run(
         .await?;
 
         if args.wait || !args.detach {
-            let output = podman
-                .force_run([
-                    "ps",
-                    "--all",
-                    "--format",
-                    "json",
-                    "--filter",
+            let containers = podman
+                .containers([
                     "label=io.podman.compose.oneoff=false",
-                    "--filter",
                     &format!("pod={}", file.name.as_ref().unwrap()),
                 ])
-                .await?;
-            let containers = serde_json::from_str::<Vec<Container>>(&output)?
+                .await?
                 .into_iter()
                 .filter_map(|mut container| {
                     container
@@ -155,21 +305,31 @@ pub(crate) async fn run(
                                 || (args.services.is_empty()
                                     && file.services.keys().contains(&service))
                             {
-                                container.names.pop_front()
+                                container.names.pop_front().map(|name| (name, service))
                             } else {
                                 None
                             }
                         })
                 })
-                .collect::<Vec<_>>();
+                .collect::<IndexMap<_, _>>();
 
             if !containers.is_empty() {
                 if args.wait {
                     let progress = Progress::new(config);
 
-                    wait_containers(podman, &progress, &containers).await?;
+                    let pending =
+                        wait_containers(podman, &progress, file, &containers, args.wait_timeout)
+                            .await?;
 
                     progress.finish();
+
+                    if !pending.is_empty() {
+                        bail!(
+                            "Services did not become ready in {}s: {}",
+                            args.wait_timeout.unwrap_or_default(),
+                            pending.iter().join(", ")
+                        );
+                    }
                 } else {
                     let mut services = if args.attach_dependencies {
                         file.services.keys().cloned().collect()
@@ -183,7 +343,11 @@ pub(crate) async fn run(
 
                     services.retain(|service| !args.no_attach.contains(service));
 
-                    eprintln!("Attaching to {}", containers.join(", "));
+                    eprintln!("Attaching to {}", containers.keys().join(", "));
+
+                    let abort = args.abort_on_container_exit
+                        || args.abort_on_container_failure
+                        || args.exit_code_from.is_some();
 
                     select! {
                         biased;
@@ -204,20 +368,64 @@ pub(crate) async fn run(
 
                             process::exit(130);
                         }
-                        _ = logs::run(
-                            logs::Args {
-                                services,
-                                follow: true,
-                                since: None,
-                                until: None,
-                                no_color: args.no_colour,
-                                no_log_prefix: args.no_log_prefix,
-                                timestamps: args.timestamps,
-                                tail: Some(0),
-                            },
-                            podman,
-                            file,
-                        ) => {}
+                        exited = wait_exit(podman, &containers, since, args.abort_on_container_failure), if abort => {
+                            let Some((service, mut code)) = exited? else {
+                                bail!("Podman stopped reporting events before a container exited");
+                            };
+
+                            eprintln!("Aborting on container exit...");
+
+                            stop::run(
+                                stop::Args {
+                                    services: Vec::new(),
+                                    timeout: args.timeout,
+                                },
+                                podman,
+                                file,
+                                config,
+                            )
+                            .await?;
+
+                            if let Some(exit_code_from) = &args.exit_code_from {
+                                if *exit_code_from != service {
+                                    let container = containers
+                                        .iter()
+                                        .find(|(_, service)| *service == exit_code_from)
+                                        .map(|(container, _)| container)
+                                        .ok_or_else(|| {
+                                            anyhow!("No container found for service \"{exit_code_from}\"")
+                                        })?;
+
+                                    code = podman.inspect_container(container).await?.exit_code;
+                                }
+                            }
+
+                            process::exit(code);
+                        }
+                        _ = async {
+                            let result = logs::run(
+                                logs::Args {
+                                    services,
+                                    follow: true,
+                                    since: None,
+                                    until: None,
+                                    no_color: args.no_colour,
+                                    no_log_prefix: args.no_log_prefix,
+                                    timestamps: args.timestamps,
+                                    tail: Some(0),
+                                },
+                                podman,
+                                file,
+                            )
+                            .await;
+
+                            // Keep waiting for the exit event once every log stream has ended
+                            if abort {
+                                future::pending::<()>().await;
+                            }
+
+                            result
+                        } => {}
                     };
                 }
             }
@@ -226,3 +434,188 @@ pub(crate) async fn run(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::podman::fake::{self, Operation};
+
+    #[tokio::test]
+    async fn detach() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&["--detach"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::Start),
+            [format!("{name}_dependency_1"), format!("{name}_app_1")]
+        );
+        assert!(engine
+            .state()
+            .containers
+            .values()
+            .all(|container| container.state == "running"));
+
+        engine.clear_calls();
+        run(fake::args(&["--detach"]), &podman, &file, &config)
+            .await
+            .unwrap();
+        assert!(engine.targets(Operation::Create).is_empty());
+
+        run(
+            fake::args(&["--detach", "--force-recreate"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(engine.targets(Operation::Create).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn wait() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        run(fake::args(&["--wait"]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::Start),
+            [format!("{name}_dependency_1"), format!("{name}_app_1")]
+        );
+        assert!(engine.targets(Operation::Wait).is_empty());
+    }
+
+    #[tokio::test]
+    async fn wait_healthy() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/wait/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        engine.state_mut().containers[&format!("{name}_web_1")].health =
+            Some(String::from("healthy"));
+
+        run(fake::args(&["--wait"]), &podman, &file, &config)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_unhealthy() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/wait/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        engine.state_mut().containers[&format!("{name}_web_1")].health =
+            Some(String::from("unhealthy"));
+
+        let err = run(fake::args(&["--wait"]), &podman, &file, &config)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!("Container \"{name}_web_1\" is unhealthy")
+        );
+    }
+
+    #[tokio::test]
+    async fn wait_timeout() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/wait/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        let err = run(
+            fake::args(&["--wait", "--wait-timeout", "1"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "Services did not become ready in 1s: web");
+        assert!(engine
+            .targets(Operation::Run)
+            .contains(&format!("healthcheck run {name}_web_1")));
+    }
+
+    #[tokio::test]
+    async fn wait_not_running() {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/wait/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        for status in ["created", "paused"] {
+            engine.state_mut().containers[&format!("{name}_worker_1")].state = String::from(status);
+
+            let err = wait_containers(
+                &podman,
+                &Progress::hidden(),
+                &file,
+                &IndexMap::from([(format!("{name}_worker_1"), String::from("worker"))]),
+                None,
+            )
+            .await
+            .unwrap_err();
+
+            assert_eq!(
+                err.to_string(),
+                format!("Container \"{name}_worker_1\" is {status} instead of running")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn exit_code_from_unknown_service() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+
+        let err = run(
+            fake::args(&["--exit-code-from", "missing"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "No such service: \"missing\"");
+        assert!(engine.state().calls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn events_ended() {
+        let (config, file, _, podman) = fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+
+        let err = run(
+            fake::args(&["--abort-on-container-exit"]),
+            &podman,
+            &file,
+            &config,
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Podman stopped reporting events before a container exited"
+        );
+    }
+}
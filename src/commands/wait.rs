@@ -0,0 +1,109 @@
+use std::future;
+
+use anyhow::{bail, Result};
+use futures::{stream::FuturesUnordered, TryStreamExt};
+
+use crate::{compose::types::Compose, podman::Podman};
+
+/// Block until service containers stop and print their exit codes
+#[derive(clap::Args, Debug)]
+#[command(next_display_order = None)]
+pub(crate) struct Args {
+    #[arg(required = true)]
+    services: Vec<String>,
+}
+
+/// Returns the exit code of the first container that exited with a non-zero code
+pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<i32> {
+    for service in &args.services {
+        if !file.services.contains_key(service) {
+            bail!("No such service: \"{service}\"");
+        }
+    }
+
+    let containers = podman
+        .containers([
+            "label=io.podman.compose.oneoff=false",
+            &format!("pod={}", file.name.as_ref().unwrap()),
+        ])
+        .await?
+        .into_iter()
+        .filter_map(|mut container| {
+            container
+                .labels
+                .and_then(|labels| labels.service)
+                .and_then(|service| {
+                    if args.services.contains(&service) {
+                        container.names.pop_front()
+                    } else {
+                        None
+                    }
+                })
+        })
+        .collect::<Vec<_>>();
+
+    containers
+        .iter()
+        .map(|container| async move {
+            anyhow::Ok((container, podman.wait_container(container).await?))
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_fold(0, |code, (container, exit_code)| {
+            println!("{container} exited with code {exit_code}");
+
+            future::ready(Ok(if code == 0 { exit_code } else { code }))
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{
+        commands::{create, start},
+        podman::fake::{self, Operation},
+    };
+
+    #[tokio::test]
+    async fn exit_code() {
+        let (config, file, engine, podman) =
+            fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+        let name = file.name.as_ref().unwrap();
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        start::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            run(fake::args(&["app", "dependency"]), &podman, &file)
+                .await
+                .unwrap(),
+            0
+        );
+
+        engine.clear_calls();
+        engine.state_mut().containers[&format!("{name}_app_1")].exit_code = 3;
+
+        assert_eq!(run(fake::args(&["app"]), &podman, &file).await.unwrap(), 3);
+        assert_eq!(engine.targets(Operation::Wait), [format!("{name}_app_1")]);
+    }
+
+    #[tokio::test]
+    async fn unknown_service() {
+        let (_, file, engine, podman) = fake::setup("tests/fixtures/ups-deps-stop/compose.yaml");
+
+        assert_eq!(
+            run(fake::args(&["missing"]), &podman, &file)
+                .await
+                .unwrap_err()
+                .to_string(),
+            "No such service: \"missing\""
+        );
+        assert!(engine.state().calls.is_empty());
+    }
+}
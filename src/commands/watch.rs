@@ -0,0 +1,549 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use futures::{stream::FuturesUnordered, TryStreamExt};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use indexmap::{IndexMap, IndexSet};
+use itertools::Itertools;
+use notify::RecursiveMode;
+use petgraph::{algo::has_path_connecting, graphmap::DiGraphMap};
+use tokio::sync::mpsc;
+
+use crate::{
+    commands::{cp, create, restart, start},
+    compose::types::{Compose, WatchAction, WatchRule},
+    config::Config,
+    podman::Podman,
+    progress::{Finish, Progress},
+    utils::STYLED_WARNING,
+};
+
+/// Watch build context for service and rebuild/refresh containers when files are updated
+#[derive(clap::Args, Debug)]
+#[command(next_display_order = None)]
+pub(crate) struct Args {
+    services: Vec<String>,
+
+    /// Do not build & start services before watching
+    #[arg(long)]
+    no_up: bool,
+}
+
+/// Watch rules of the given services, or of all services if none are given
+fn rules<'a>(
+    file: &'a Compose,
+    services: &[String],
+) -> Result<Vec<(&'a String, &'a WatchRule, GlobSet)>> {
+    file.services
+        .iter()
+        .filter(|(name, _)| services.is_empty() || services.contains(name))
+        .flat_map(|(name, service)| {
+            service
+                .develop
+                .iter()
+                .flat_map(|develop| &develop.watch)
+                .map(move |rule| {
+                    let mut ignore = GlobSetBuilder::new();
+
+                    for pattern in &rule.ignore {
+                        ignore.add(Glob::new(pattern)?);
+                    }
+
+                    anyhow::Ok((name, rule, ignore.build()?))
+                })
+        })
+        .collect()
+}
+
+/// What a service does after files it watches have changed
+#[derive(PartialEq, Eq, Debug)]
+enum Action {
+    Rebuild,
+    /// Copy changed files to their target in the containers, then restart them if requested
+    Sync {
+        files: Vec<(PathBuf, String)>,
+        restart: bool,
+    },
+}
+
+/// Map changed paths to the action of each service watching them, skipping ignored paths
+fn actions<'a>(
+    rules: &[(&'a String, &'a WatchRule, GlobSet)],
+    paths: &IndexSet<PathBuf>,
+) -> IndexMap<&'a String, Action> {
+    let mut actions = IndexMap::new();
+
+    for (service, rule, ignore) in rules {
+        for path in paths {
+            let Some(relative) = path
+                .strip_prefix(&rule.path)
+                .ok()
+                .filter(|relative| !ignore.is_match(relative))
+            else {
+                continue;
+            };
+            let action = actions.entry(*service).or_insert(Action::Sync {
+                files: Vec::new(),
+                restart: false,
+            });
+
+            match (rule.action, &mut *action) {
+                (_, Action::Rebuild) => {}
+                (WatchAction::Rebuild, _) => *action = Action::Rebuild,
+                (WatchAction::Sync | WatchAction::SyncRestart, Action::Sync { files, restart }) => {
+                    if let Some(target) = &rule.target {
+                        // A rule watching a single file syncs it to the target itself
+                        let destination = if relative.as_os_str().is_empty() {
+                            target.clone()
+                        } else {
+                            Path::new(target)
+                                .join(relative)
+                                .to_string_lossy()
+                                .to_string()
+                        };
+
+                        files.push((path.clone(), destination));
+                    }
+
+                    *restart |= rule.action == WatchAction::SyncRestart;
+                }
+            }
+        }
+    }
+
+    actions
+}
+
+async fn sync_files(
+    podman: &Podman,
+    progress: &Progress,
+    containers: &[String],
+    files: &[(PathBuf, String)],
+) -> Result<()> {
+    containers
+        .iter()
+        .map(|container| async move {
+            let spinner = progress.add_spinner(format!("Container {container}"), "Syncing");
+
+            async {
+                for (path, destination) in files {
+                    if path.is_dir() {
+                        podman
+                            .run(["exec", container, "mkdir", "-p", destination])
+                            .await?;
+                    } else if path.exists() {
+                        cp::copy(
+                            podman,
+                            &path.to_string_lossy(),
+                            &format!("{container}:{destination}"),
+                            false,
+                        )
+                        .await?;
+                    } else {
+                        podman
+                            .run(["exec", container, "rm", "-rf", destination])
+                            .await?;
+                    }
+                }
+
+                anyhow::Ok(())
+            }
+            .await
+            .finish_with_message(spinner, "Synced")
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect::<Vec<_>>()
+        .await
+        .map(|_| ())
+}
+
+async fn rebuild_service(
+    podman: &Podman,
+    file: &Compose,
+    config: &Config,
+    service: &String,
+) -> Result<()> {
+    let dependencies = file
+        .services
+        .iter()
+        .flat_map(|(to, service)| service.depends_on.keys().map(move |from| (from, to, ())))
+        .collect::<DiGraphMap<_, _>>();
+    let services = file
+        .services
+        .keys()
+        .filter(|name| {
+            *name == service
+                || (dependencies.contains_node(service)
+                    && dependencies.contains_node(name)
+                    && has_path_connecting(&dependencies, service, name, None))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    create::run(
+        create::Args {
+            services: vec![service.clone()],
+            pull: None,
+            build: true,
+            no_build: false,
+            force_recreate: true,
+            no_recreate: false,
+            no_deps: true,
+            always_recreate_deps: false,
+            renew_anon_volumes: false,
+            remove_orphans: false,
+            scale: Vec::new(),
+        },
+        podman,
+        file,
+        config,
+    )
+    .await?;
+
+    // Dependents are recreated along with the service, so they are started again too
+    start::run(
+        start::Args {
+            services,
+            wait_timeout: None,
+            no_deps: true,
+        },
+        podman,
+        file,
+        config,
+    )
+    .await
+}
+
+async fn apply(
+    podman: &Podman,
+    file: &Compose,
+    config: &Config,
+    containers: &[String],
+    service: &String,
+    action: &Action,
+) -> Result<()> {
+    match action {
+        Action::Rebuild => {
+            eprintln!("Rebuilding service \"{service}\" after changes were detected");
+
+            rebuild_service(podman, file, config, service).await
+        }
+        Action::Sync { files, restart } => {
+            eprintln!("Syncing service \"{service}\" after changes were detected");
+
+            let progress = Progress::new(config);
+
+            sync_files(podman, &progress, containers, files).await?;
+
+            progress.finish();
+
+            if *restart {
+                restart::run(
+                    restart::Args {
+                        services: vec![service.clone()],
+                        timeout: None,
+                    },
+                    podman,
+                    file,
+                    config,
+                )
+                .await?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+pub(crate) async fn run(
+    args: Args,
+    podman: &Podman,
+    file: &Compose,
+    config: &Config,
+) -> Result<()> {
+    for service in &args.services {
+        if !file.services.contains_key(service) {
+            bail!("No such service: \"{service}\"");
+        }
+    }
+
+    let rules = rules(file, &args.services)?;
+
+    if rules.is_empty() {
+        bail!("None of the selected services is configured for watch, consider setting a `develop` section");
+    }
+
+    if !args.no_up {
+        let services = rules
+            .iter()
+            .map(|(service, _, _)| (*service).clone())
+            .unique()
+            .collect::<Vec<_>>();
+
+        create::run(
+            create::Args {
+                services: services.clone(),
+                pull: None,
+                build: false,
+                no_build: false,
+                force_recreate: false,
+                no_recreate: false,
+                no_deps: false,
+                always_recreate_deps: false,
+                renew_anon_volumes: false,
+                remove_orphans: false,
+                scale: Vec::new(),
+            },
+            podman,
+            file,
+            config,
+        )
+        .await?;
+
+        start::run(
+            start::Args {
+                services,
+                wait_timeout: None,
+                no_deps: false,
+            },
+            podman,
+            file,
+            config,
+        )
+        .await?;
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut debouncer =
+        notify_debouncer_mini::new_debouncer(Duration::from_millis(500), move |result| {
+            tx.send(result).ok();
+        })?;
+
+    for (_, rule, _) in &rules {
+        debouncer
+            .watcher()
+            .watch(&rule.path, RecursiveMode::Recursive)?;
+    }
+
+    eprintln!(
+        "Watching {}",
+        rules
+            .iter()
+            .map(|(_, rule, _)| rule.path.display())
+            .unique_by(ToString::to_string)
+            .join(", ")
+    );
+
+    while let Some(events) = rx.recv().await {
+        // A watched directory that is removed or unreadable should not end the session
+        let events = match events {
+            Ok(events) => events,
+            Err(err) => {
+                eprintln!("{} {err}", *STYLED_WARNING);
+
+                continue;
+            }
+        };
+        let actions = actions(
+            &rules,
+            &events.into_iter().map(|event| event.path).collect(),
+        );
+
+        if actions.is_empty() {
+            continue;
+        }
+
+        let containers = podman
+            .containers([
+                "label=io.podman.compose.oneoff=false",
+                &format!("pod={}", file.name.as_ref().unwrap()),
+            ])
+            .await?
+            .into_iter()
+            .filter_map(|mut container| {
+                container
+                    .labels
+                    .and_then(|labels| labels.service)
+                    .and_then(|service| container.names.pop_front().map(|name| (service, name)))
+            })
+            .into_group_map();
+
+        for (service, action) in &actions {
+            apply(
+                podman,
+                file,
+                config,
+                containers.get(*service).map_or(&[], Vec::as_slice),
+                service,
+                action,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::podman::fake::{self, Operation};
+
+    async fn setup() -> (Config, Compose, fake::Fake, Podman) {
+        let (config, file, engine, podman) = fake::setup("tests/fixtures/watch/compose.yaml");
+
+        create::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        start::run(fake::args(&[]), &podman, &file, &config)
+            .await
+            .unwrap();
+        engine.clear_calls();
+
+        (config, file, engine, podman)
+    }
+
+    fn paths(config: &Config, paths: &[&str]) -> IndexSet<PathBuf> {
+        paths
+            .iter()
+            .map(|path| config.project_directory.join(path))
+            .collect()
+    }
+
+    #[test]
+    fn ignore() {
+        let (config, file) = fake::project("tests/fixtures/watch/compose.yaml");
+        let rules = rules(&file, &[]).unwrap();
+
+        assert_eq!(
+            actions(&rules, &paths(&config, &["src/.index.html.swp"])),
+            IndexMap::from([(
+                &String::from("proxy"),
+                Action::Sync {
+                    files: vec![(
+                        config.project_directory.join("src/.index.html.swp"),
+                        String::from("/srv/.index.html.swp")
+                    )],
+                    restart: true,
+                }
+            )])
+        );
+        assert_eq!(
+            actions(&rules, &paths(&config, &["compose.yaml"])),
+            IndexMap::new()
+        );
+    }
+
+    #[tokio::test]
+    async fn sync() {
+        let (config, file, engine, podman) = setup().await;
+        let name = file.name.as_ref().unwrap();
+        let web = String::from("web");
+        let rules = rules(&file, &[String::from("web")]).unwrap();
+        let actions = actions(&rules, &paths(&config, &["src/index.html", "src/removed"]));
+
+        assert_eq!(
+            actions,
+            IndexMap::from([(
+                &web,
+                Action::Sync {
+                    files: vec![
+                        (
+                            config.project_directory.join("src/index.html"),
+                            String::from("/usr/share/nginx/html/index.html")
+                        ),
+                        (
+                            config.project_directory.join("src/removed"),
+                            String::from("/usr/share/nginx/html/removed")
+                        ),
+                    ],
+                    restart: false,
+                }
+            )])
+        );
+
+        apply(
+            &podman,
+            &file,
+            &config,
+            &[format!("{name}_web_1")],
+            &web,
+            &actions[&web],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            engine.targets(Operation::Run),
+            [
+                shell_words::join([
+                    "cp",
+                    &config
+                        .project_directory
+                        .join("src/index.html")
+                        .to_string_lossy(),
+                    &format!("{name}_web_1:/usr/share/nginx/html/index.html"),
+                ]),
+                format!("exec {name}_web_1 rm -rf /usr/share/nginx/html/removed"),
+            ]
+        );
+        assert_eq!(engine.targets(Operation::Stop), Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn sync_restart() {
+        let (config, file, engine, podman) = setup().await;
+        let name = file.name.as_ref().unwrap();
+        let proxy = String::from("proxy");
+        let rules = rules(&file, &[]).unwrap();
+        let actions = actions(&rules, &paths(&config, &["src/index.html"]));
+
+        apply(
+            &podman,
+            &file,
+            &config,
+            &[format!("{name}_proxy_1")],
+            &proxy,
+            &actions[&proxy],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(engine.targets(Operation::Run).len(), 1);
+        assert_eq!(engine.targets(Operation::Stop), [format!("{name}_proxy_1")]);
+        assert_eq!(
+            engine.targets(Operation::Start),
+            [format!("{name}_proxy_1")]
+        );
+    }
+
+    #[tokio::test]
+    async fn rebuild() {
+        let (config, file, engine, podman) = setup().await;
+        let name = file.name.as_ref().unwrap();
+        let web = String::from("web");
+        let rules = rules(&file, &[]).unwrap();
+        let actions = actions(&rules, &paths(&config, &["Dockerfile", "src/index.html"]));
+
+        assert_eq!(actions[&web], Action::Rebuild);
+
+        apply(&podman, &file, &config, &[], &web, &actions[&web])
+            .await
+            .unwrap();
+
+        // Dependents go along with the recreated container, dependencies are left alone
+        assert_eq!(
+            engine.targets(Operation::Create),
+            [format!("{name}_web_1"), format!("{name}_proxy_1")]
+        );
+        assert_eq!(
+            engine.targets(Operation::Start),
+            [format!("{name}_web_1"), format!("{name}_proxy_1")]
+        );
+    }
+}
@@ -1,26 +1,50 @@
 automod::dir!(pub(crate) "src/compose");
 
 use std::{
+    cell::RefCell,
     env::{self, VarError},
     fs,
     io::{self, Read},
+    mem,
+    path::{Path, PathBuf},
 };
 
 use anyhow::{anyhow, bail, Context, Error, Result};
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
 use petgraph::{algo::tarjan_scc, graphmap::DiGraphMap};
-use serde_yaml::Value;
+use serde::Serialize;
+use serde_yaml::{value::TaggedValue, Value};
 
 use self::{
     parser::{State, Token, Var},
-    types::{Compose, Condition, ServiceVolumeType},
+    types::{Compose, Service, ServiceVolumeType, WatchAction},
 };
 use crate::{
     config::Config,
     utils::{regex, STYLED_WARNING},
 };
 
+thread_local! {
+    static ENVIRONMENT: RefCell<IndexMap<String, String>> = RefCell::new(IndexMap::new());
+}
+
+fn with_environment<T>(environment: IndexMap<String, String>, f: impl FnOnce() -> T) -> T {
+    let previous = ENVIRONMENT.replace(environment);
+    let result = f();
+    ENVIRONMENT.set(previous);
+
+    result
+}
+
+fn lookup(name: &str) -> Result<String, VarError> {
+    env::var(name).or_else(|err| {
+        ENVIRONMENT
+            .with_borrow(|environment| environment.get(name).cloned())
+            .ok_or(err)
+    })
+}
+
 fn evaluate(tokens: Vec<Token>) -> Result<String> {
     tokens
         .into_iter()
@@ -28,8 +52,8 @@ fn evaluate(tokens: Vec<Token>) -> Result<String> {
             Token::Str(string) => Ok(string),
             Token::Var(name, var) => match var {
                 Some(Var::Default(state, tokens)) => match state {
-                    State::Set => env::var(name),
-                    State::SetAndNonEmpty => env::var(name).and_then(|var| {
+                    State::Set => lookup(&name),
+                    State::SetAndNonEmpty => lookup(&name).and_then(|var| {
                         if var.is_empty() {
                             Err(VarError::NotPresent)
                         } else {
@@ -39,8 +63,8 @@ fn evaluate(tokens: Vec<Token>) -> Result<String> {
                 }
                 .or_else(|_| evaluate(tokens)),
                 Some(Var::Err(state, tokens)) => match state {
-                    State::Set => env::var(&name),
-                    State::SetAndNonEmpty => env::var(&name).and_then(|var| {
+                    State::Set => lookup(&name),
+                    State::SetAndNonEmpty => lookup(&name).and_then(|var| {
                         if var.is_empty() {
                             Err(VarError::NotPresent)
                         } else {
@@ -58,8 +82,8 @@ fn evaluate(tokens: Vec<Token>) -> Result<String> {
                     })
                 }),
                 Some(Var::Replace(state, tokens)) => match state {
-                    State::Set => env::var(name),
-                    State::SetAndNonEmpty => env::var(name).and_then(|var| {
+                    State::Set => lookup(&name),
+                    State::SetAndNonEmpty => lookup(&name).and_then(|var| {
                         if var.is_empty() {
                             Err(VarError::NotPresent)
                         } else {
@@ -68,7 +92,7 @@ fn evaluate(tokens: Vec<Token>) -> Result<String> {
                     }),
                 }
                 .map_or_else(|_| Ok(String::new()), |_| evaluate(tokens)),
-                None => Ok(env::var(&name).unwrap_or_else(|_| {
+                None => Ok(lookup(&name).unwrap_or_else(|_| {
                     eprintln!(
                         "{} The \"{name}\" variable is not set, defaulting to a blank string",
                         *STYLED_WARNING
@@ -83,7 +107,14 @@ fn evaluate(tokens: Vec<Token>) -> Result<String> {
 }
 
 fn interpolate(value: &Value) -> Result<Value> {
-    if let Some(value) = value.as_str() {
+    if let Value::Tagged(tagged) = value {
+        interpolate(&tagged.value).map(|value| {
+            Value::Tagged(Box::new(TaggedValue {
+                tag: tagged.tag.clone(),
+                value,
+            }))
+        })
+    } else if let Some(value) = value.as_str() {
         parser::parse(value).and_then(evaluate).map(Value::String)
     } else if let Some(values) = value.as_sequence() {
         values.iter().map(interpolate).collect()
@@ -102,32 +133,259 @@ fn interpolate(value: &Value) -> Result<Value> {
     }
 }
 
-pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
-    let contents = config
-        .files
-        .iter()
-        .map(|path| {
-            if path.as_os_str() == "-" {
-                let mut content = String::new();
-                let mut stdin = io::stdin();
+fn read(path: &Path) -> Result<String> {
+    if path.as_os_str() == "-" {
+        let mut content = String::new();
+        let mut stdin = io::stdin();
 
-                stdin.read_to_string(&mut content)?;
+        stdin.read_to_string(&mut content)?;
 
-                Ok((path, content))
-            } else {
-                fs::read_to_string(path)
-                    .with_context(|| format!("{} not found", path.display()))
-                    .map(|content| (path, content))
+        Ok(content)
+    } else {
+        fs::read_to_string(path).with_context(|| format!("{} not found", path.display()))
+    }
+}
+
+fn interpolate_file(content: &Value) -> Result<Value> {
+    interpolate(content).map_err(|err| match err.chain().collect::<Vec<_>>().split_last() {
+        Some((err, props)) => {
+            anyhow!("{}: {err}", props.iter().join("."))
+        }
+        None => err,
+    })
+}
+
+fn deserialize(path: &Path, content: &Value) -> Result<Compose> {
+    let mut content = content.clone();
+    let tags = types::untag(&mut content)?;
+    let extensions = types::extensions(&content);
+    let service_extensions = content
+        .get("services")
+        .and_then(Value::as_mapping)
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, service)| {
+            name.as_str()
+                .map(|name| (name.to_string(), types::extensions(service)))
+        })
+        .collect::<IndexMap<_, _>>();
+    let content = serde_yaml::to_string(&content)?;
+    let mut unused = IndexSet::new();
+
+    let mut file: Compose =
+        serde_ignored::deserialize(serde_yaml::Deserializer::from_str(&content), |path| {
+            if !path
+                .to_string()
+                .rsplit('.')
+                .next()
+                .unwrap_or_default()
+                .starts_with("x-")
+            {
+                unused.insert(path.to_string());
             }
         })
+        .with_context(|| {
+            format!(
+                "{} does not follow the Compose specification",
+                path.display()
+            )
+        })?;
+
+    if !unused.is_empty() {
+        eprintln!(
+            "{} Unsupported/unknown properties in {}: {}",
+            *STYLED_WARNING,
+            path.display(),
+            unused.into_iter().join(", ")
+        );
+    }
+
+    file.tags = tags;
+    file.extensions = extensions;
+
+    for (name, extensions) in service_extensions {
+        if let Some(service) = file.services.get_mut(&name) {
+            service.extensions = extensions;
+        }
+    }
+
+    Ok(file)
+}
+
+fn load(path: &Path, directory: &Path, no_interpolate: bool) -> Result<Compose> {
+    let mut content = serde_yaml::from_str::<Value>(&read(path)?)?;
+    content.apply_merge()?;
+
+    if !no_interpolate {
+        content = interpolate_file(&content)?;
+    }
+
+    types::with_working_directory(directory, || deserialize(path, &content))
+}
+
+fn merge_included<T: Serialize>(
+    kind: &str,
+    resources: &mut IndexMap<String, T>,
+    included: IndexMap<String, T>,
+    path: &Path,
+) -> Result<()> {
+    for (name, resource) in included {
+        if let Some(existing) = resources.get(&name) {
+            if serde_yaml::to_value(existing)? != serde_yaml::to_value(&resource)? {
+                bail!(
+                    "{kind} \"{name}\" included from {} conflicts with an existing definition",
+                    path.display()
+                );
+            }
+        } else {
+            resources.insert(name, resource);
+        }
+    }
+
+    Ok(())
+}
+
+fn include(file: &mut Compose, no_interpolate: bool, visited: &mut Vec<PathBuf>) -> Result<()> {
+    for include in mem::take(&mut file.include) {
+        let project_directory = include.project_directory.clone().unwrap_or_else(|| {
+            include.path[0]
+                .parent()
+                .unwrap_or_else(|| Path::new("/"))
+                .to_path_buf()
+        });
+        let env_files = if include.env_file.is_empty() {
+            Some(project_directory.join(".env"))
+                .filter(|env_file| env_file.is_file())
+                .into_iter()
+                .collect()
+        } else {
+            include.env_file
+        };
+        let environment = env_files
+            .iter()
+            .map(|env_file| {
+                dotenvy::from_path_iter(env_file)
+                    .with_context(|| format!("{} not found", env_file.display()))
+            })
+            .flatten_ok()
+            .map(|var| var.and_then(|var| var.map_err(Error::from)))
+            .collect::<Result<IndexMap<_, _>>>()?;
+        let mut included_file = Compose::new();
+
+        for path in &include.path {
+            if let Some(i) = visited.iter().position(|visited| visited == path) {
+                bail!(
+                    "Cycles found: {} -> {}",
+                    visited[i..].iter().map(|path| path.display()).join(" -> "),
+                    path.display()
+                );
+            }
+
+            visited.push(path.clone());
+
+            let mut other = with_environment(environment.clone(), || {
+                load(path, &project_directory, no_interpolate)
+            })?;
+            self::include(&mut other, no_interpolate, visited)?;
+
+            visited.pop();
+
+            included_file.merge(other)?;
+        }
+
+        let path = &include.path[0];
+
+        merge_included("Service", &mut file.services, included_file.services, path)?;
+        merge_included("Network", &mut file.networks, included_file.networks, path)?;
+        merge_included("Volume", &mut file.volumes, included_file.volumes, path)?;
+        merge_included("Secret", &mut file.secrets, included_file.secrets, path)?;
+        merge_included("Config", &mut file.configs, included_file.configs, path)?;
+    }
+
+    Ok(())
+}
+
+fn resolve_extends(
+    file: &Compose,
+    path: Option<&Path>,
+    name: &str,
+    no_interpolate: bool,
+    visited: &mut Vec<(Option<PathBuf>, String)>,
+) -> Result<Service> {
+    let service = &file.services[name];
+    let Some(extends) = &service.extends else {
+        return Ok(service.clone());
+    };
+
+    let key = (path.map(Path::to_path_buf), name.to_string());
+
+    if let Some(i) = visited.iter().position(|visited| *visited == key) {
+        bail!(
+            "Cycles found: {} -> {name}",
+            visited[i..].iter().map(|(_, name)| name).join(" -> ")
+        );
+    }
+
+    visited.push(key);
+
+    let mut base = if let Some(extends_path) = &extends.file {
+        let extends_file = load(
+            extends_path,
+            extends_path.parent().unwrap_or_else(|| Path::new("/")),
+            no_interpolate,
+        )?;
+
+        if !extends_file.services.contains_key(&extends.service) {
+            bail!(
+                "Service \"{name}\" extends undefined service \"{}\" in {}",
+                extends.service,
+                extends_path.display()
+            );
+        }
+
+        resolve_extends(
+            &extends_file,
+            Some(extends_path),
+            &extends.service,
+            no_interpolate,
+            visited,
+        )?
+    } else {
+        if !file.services.contains_key(&extends.service) {
+            bail!(
+                "Service \"{name}\" extends undefined service \"{}\"",
+                extends.service
+            );
+        }
+
+        resolve_extends(file, path, &extends.service, no_interpolate, visited)?
+    };
+
+    visited.pop();
+
+    base.merge(&Service {
+        extends: None,
+        ..service.clone()
+    })?;
+    base.extends = None;
+
+    Ok(base)
+}
+
+pub(crate) fn parse(config: &Config, no_interpolate: bool, services: &[String]) -> Result<Compose> {
+    let contents = config
+        .files
+        .iter()
+        .map(|path| read(path).map(|content| (path, content)))
         .collect::<Result<Vec<_>, _>>()?;
     let files = contents
         .into_iter()
         .enumerate()
         .map(|(i, (path, content))| {
             serde_yaml::from_str(&content)
+                .and_then(|mut content: Value| content.apply_merge().map(|()| content))
                 .map_err(Error::from)
-                .map(|mut content: Value| {
+                .map(|mut content| {
                     if let Some(values) = content.as_mapping_mut() {
                         let name = if config.project_name.is_some() {
                             config.project_name.clone()
@@ -179,54 +437,61 @@ pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
                 content
             } else {
                 content.and_then(|(path, content)| {
-                    interpolate(&content)
-                        .map_err(|err| match err.chain().collect::<Vec<_>>().split_last() {
-                            Some((err, props)) => {
-                                anyhow!("{}: {err}", props.iter().join("."))
-                            }
-                            None => err,
-                        })
-                        .map(|content| (path, content))
+                    interpolate_file(&content).map(|content| (path, content))
                 })
             }
         })
         .map(|content| {
             content.and_then(|(path, content)| {
-                serde_yaml::to_string(&content)
-                    .map_err(Error::from)
-                    .map(|content| (path, content))
-            })
-        })
-        .map(|content| {
-            content.and_then(|(path, content)| {
-                let mut unused = IndexSet::new();
-
-                serde_ignored::deserialize(serde_yaml::Deserializer::from_str(&content), |path| {
-                    unused.insert(path.to_string());
+                types::with_working_directory(&config.project_directory, || {
+                    deserialize(path, &content)
                 })
-                .with_context(|| {
-                    format!(
-                        "{} does not follow the Compose specification",
-                        path.display()
-                    )
+                .and_then(|mut file| {
+                    include(&mut file, no_interpolate, &mut vec![path.clone()])?;
+
+                    Ok(file)
                 })
-                .map(|file: Compose| (path, file, unused))
             })
         })
         .collect::<Result<Vec<_>, _>>()?;
     let mut combined_file = Compose::new();
 
-    for (path, file, unused) in files {
-        if !unused.is_empty() {
-            eprintln!(
-                "{} Unsupported/unknown properties in {}: {}",
-                *STYLED_WARNING,
-                path.display(),
-                unused.into_iter().join(", ")
-            );
-        }
+    for file in files {
+        combined_file.merge(file)?;
+    }
+
+    combined_file.services = combined_file
+        .services
+        .keys()
+        .map(|name| {
+            resolve_extends(&combined_file, None, name, no_interpolate, &mut Vec::new())
+                .map(|service| (name.clone(), service))
+        })
+        .collect::<Result<_>>()?;
+
+    // Naming a service enables its profiles and the ones of the services it requires
+    let mut profiles = config.profiles.iter().cloned().collect::<IndexSet<_>>();
+    let mut requested = services
+        .iter()
+        .filter(|service| combined_file.services.contains_key(*service))
+        .collect::<IndexSet<_>>();
+    let mut index = 0;
 
-        combined_file.merge(file);
+    while let Some(name) = requested.get_index(index) {
+        let service = &combined_file.services[*name];
+
+        profiles.extend(service.profiles.iter().cloned());
+        requested.extend(
+            service
+                .depends_on
+                .iter()
+                .filter(|(dependency, options)| {
+                    options.required.unwrap_or(true)
+                        && combined_file.services.contains_key(*dependency)
+                })
+                .map(|(dependency, _)| dependency),
+        );
+        index += 1;
     }
 
     combined_file.services.retain(|_, service| {
@@ -235,7 +500,7 @@ pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
         }
 
         for profile in &service.profiles {
-            if config.profiles.contains(profile) {
+            if profiles.contains(profile) {
                 return true;
             }
         }
@@ -243,6 +508,19 @@ pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
         false
     });
 
+    let services = combined_file
+        .services
+        .keys()
+        .cloned()
+        .collect::<IndexSet<_>>();
+
+    for service in combined_file.services.values_mut() {
+        // Optional dependencies may have been disabled by a profile
+        service.depends_on.retain(|dependency, options| {
+            options.required.unwrap_or(true) || services.contains(dependency)
+        });
+    }
+
     let all_networks = combined_file
         .services
         .values()
@@ -264,7 +542,18 @@ pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
     let all_secrets = combined_file
         .services
         .values()
-        .flat_map(|service| service.secrets.iter().map(|secret| &secret.source))
+        .flat_map(|service| {
+            service
+                .secrets
+                .iter()
+                .chain(service.build.iter().flat_map(|build| &build.secrets))
+                .map(|secret| &secret.source)
+        })
+        .collect::<IndexSet<_>>();
+    let all_configs = combined_file
+        .services
+        .values()
+        .flat_map(|service| service.configs.iter().map(|config| &config.source))
         .collect::<IndexSet<_>>();
 
     combined_file
@@ -310,6 +599,19 @@ pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
         });
     }
 
+    combined_file
+        .configs
+        .retain(|config, _| all_configs.contains(config));
+
+    for (name, config) in &mut combined_file.configs {
+        config.name.get_or_insert_with(|| {
+            match (config.external.unwrap_or_default(), &combined_file.name) {
+                (false, Some(project_name)) => format!("{project_name}_{name}"),
+                _ => name.clone(),
+            }
+        });
+    }
+
     for (name, service) in &combined_file.services {
         if service.scale.is_some() {
             eprintln!(
@@ -346,19 +648,31 @@ pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
             );
         }
 
-        if service
-            .depends_on
-            .values()
-            .any(|dependency| dependency.condition != Condition::Started)
-        {
-            eprintln!(
-                "{} \"service_healthy\" and \"service_completed_successfully\" are unsupported and will degrade to \"service_started\"",
-                *STYLED_WARNING
-            );
+        if service.image.is_none() && service.build.is_none() {
+            bail!("Service \"{name}\" does not have an image or a build context specified");
+        }
+
+        if let Some(build) = &service.build {
+            if build.dockerfile.is_some() && build.dockerfile_inline.is_some() {
+                bail!("Service \"{name}\" cannot have both a Dockerfile and an inline Dockerfile");
+            }
         }
 
-        if service.image.is_none() {
-            bail!("Service \"{name}\" does not have an image specified");
+        for rule in service.develop.iter().flat_map(|develop| &develop.watch) {
+            match rule.action {
+                WatchAction::Sync | WatchAction::SyncRestart if rule.target.is_none() => {
+                    bail!(
+                        "Service \"{name}\" cannot sync {} without a target",
+                        rule.path.display()
+                    );
+                }
+                WatchAction::Rebuild if service.build.is_none() => {
+                    bail!(
+                        "Service \"{name}\" cannot be rebuilt on changes without a build section"
+                    );
+                }
+                _ => {}
+            }
         }
 
         if service.network_mode.is_some() && service.networks.keys().any(|key| key != "default") {
@@ -371,15 +685,7 @@ pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
             bail!("Service \"{name}\" cannot have port mappings due to host network mode");
         }
 
-        if service.container_name.is_some()
-            && service
-                .deploy
-                .as_ref()
-                .and_then(|deploy| deploy.replicas)
-                .or(service.scale)
-                .unwrap_or(1)
-                > 1
-        {
+        if service.container_name.is_some() && service.replicas() > 1 {
             bail!(
                 "Service \"{name}\" cannot scale beyond one container as it has a container name"
             );
@@ -399,7 +705,7 @@ pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
 
         for network in service.networks.keys() {
             if !combined_file.networks.contains_key(network) {
-                bail!("Service \"{name}\" refers to undefined network \"{network}\"",);
+                bail!("Service \"{name}\" refers to undefined network \"{network}\"");
             }
         }
 
@@ -416,7 +722,11 @@ pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
             }
         }
 
-        for secret in &service.secrets {
+        for secret in service
+            .secrets
+            .iter()
+            .chain(service.build.iter().flat_map(|build| &build.secrets))
+        {
             if !combined_file.secrets.contains_key(&secret.source) {
                 bail!(
                     "Service \"{name}\" refers to undefined secret \"{}\"",
@@ -424,6 +734,15 @@ pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
                 );
             }
         }
+
+        for config in &service.configs {
+            if !combined_file.configs.contains_key(&config.source) {
+                bail!(
+                    "Service \"{name}\" refers to undefined config \"{}\"",
+                    config.source
+                );
+            }
+        }
     }
 
     for (name, network) in &combined_file.networks {
@@ -469,6 +788,22 @@ pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
         }
     }
 
+    for (name, config) in &combined_file.configs {
+        if [
+            config.file.is_some(),
+            config.environment.is_some(),
+            config.content.is_some(),
+            config.external.unwrap_or_default(),
+        ]
+        .into_iter()
+        .filter(|set| *set)
+        .count()
+            > 1
+        {
+            bail!("Conflicting parameters specified for config \"{name}\"");
+        }
+    }
+
     let dependencies = combined_file
         .services
         .iter()
@@ -497,6 +832,7 @@ mod tests {
     use std::path::PathBuf;
 
     use assert_matches::assert_matches;
+    use path_absolutize::Absolutize;
     use pretty_assertions::assert_eq;
     use serde_yaml::Value;
     use test_generator::test_resources;
@@ -507,10 +843,238 @@ mod tests {
     fn parse(resource: &str) {
         let config = Config {
             files: vec![PathBuf::from(resource)],
+            project_directory: Path::new(resource)
+                .parent()
+                .unwrap()
+                .absolutize()
+                .unwrap()
+                .to_path_buf(),
+            ..Config::default()
+        };
+
+        assert_matches!(super::parse(&config, false, &[]), Ok(_));
+    }
+
+    #[test]
+    fn extends() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/extends/compose.yaml")],
+            project_directory: Path::new("tests/fixtures/extends")
+                .absolutize()
+                .unwrap()
+                .to_path_buf(),
+            ..Config::default()
+        };
+        let file = super::parse(&config, false, &[]).unwrap();
+
+        let local = &file.services["local"];
+        assert_eq!(local.image.as_deref(), Some("busybox"));
+        assert!(local.extends.is_none());
+        assert_eq!(local.environment["DEBUG"].as_deref(), Some("1"));
+
+        let web = &file.services["web"];
+        assert_eq!(web.image.as_deref(), Some("busybox"));
+        assert_eq!(web.environment["LEVEL"].as_deref(), Some("web"));
+        assert_eq!(web.labels["tier"], "web");
+        assert_matches!(
+            &web.volumes[0].r#type,
+            ServiceVolumeType::Bind(source)
+                if *source == Path::new("tests/fixtures/extends/common/data").absolutize().unwrap()
+        );
+    }
+
+    #[test]
+    fn optional_dependencies() {
+        let config = Config {
+            files: vec![PathBuf::from(
+                "tests/fixtures/optional-dependencies/compose.yaml",
+            )],
+            project_directory: Path::new("tests/fixtures/optional-dependencies")
+                .absolutize()
+                .unwrap()
+                .to_path_buf(),
+            ..Config::default()
+        };
+        let file = super::parse(&config, false, &[]).unwrap();
+
+        let app = &file.services["app"];
+        assert_eq!(app.depends_on.keys().collect::<Vec<_>>(), ["db"]);
+        assert_eq!(app.depends_on["db"].restart, Some(true));
+
+        let file = super::parse(
+            &Config {
+                profiles: vec![String::from("cache")],
+                ..config
+            },
+            false,
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            file.services["app"].depends_on.keys().collect::<Vec<_>>(),
+            ["db", "cache"]
+        );
+    }
+
+    #[test]
+    fn named_profiles() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/profiles/compose.yaml")],
+            project_directory: Path::new("tests/fixtures/profiles")
+                .absolutize()
+                .unwrap()
+                .to_path_buf(),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            super::parse(&config, false, &[])
+                .unwrap()
+                .services
+                .keys()
+                .collect::<Vec<_>>(),
+            ["web"]
+        );
+        assert_eq!(
+            super::parse(&config, false, &[String::from("debug-shell")])
+                .unwrap()
+                .services
+                .keys()
+                .collect::<Vec<_>>(),
+            ["web", "debug-shell", "tools", "debug-proxy"]
+        );
+    }
+
+    #[test]
+    fn develop_watch() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/watch/compose.yaml")],
+            project_directory: Path::new("tests/fixtures/watch")
+                .absolutize()
+                .unwrap()
+                .to_path_buf(),
+            ..Config::default()
+        };
+        let file = super::parse(&config, false, &[]).unwrap();
+
+        let rules = &file.services["web"].develop.as_ref().unwrap().watch;
+        assert_eq!(
+            rules[0].path,
+            Path::new("tests/fixtures/watch/src").absolutize().unwrap()
+        );
+        assert_eq!(rules[0].action, WatchAction::Sync);
+        assert_eq!(rules[0].target.as_deref(), Some("/usr/share/nginx/html"));
+        assert_eq!(rules[0].ignore, ["*.swp"]);
+        assert_eq!(rules[1].action, WatchAction::Rebuild);
+
+        let rules = &file.services["proxy"].develop.as_ref().unwrap().watch;
+        assert_eq!(rules[0].action, WatchAction::SyncRestart);
+    }
+
+    #[test]
+    fn include() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/include/compose.yaml")],
+            project_directory: Path::new("tests/fixtures/include")
+                .absolutize()
+                .unwrap()
+                .to_path_buf(),
+            ..Config::default()
+        };
+        let file = temp_env::with_var("POSTGRES_VERSION", None::<&str>, || {
+            super::parse(&config, false, &[]).unwrap()
+        });
+
+        let db = &file.services["db"];
+        assert_eq!(db.image.as_deref(), Some("postgres:16"));
+        assert_matches!(
+            &db.volumes[0].r#type,
+            ServiceVolumeType::Bind(source)
+                if *source == Path::new("tests/fixtures/include/backend/data").absolutize().unwrap()
+        );
+        assert!(file.volumes.contains_key("db"));
+        assert!(file.include.is_empty());
+    }
+
+    #[test]
+    fn include_conflict() {
+        let mut file = serde_yaml::from_str::<Compose>(
+            "services:\n  db:\n    image: postgres\n  web:\n    image: busybox\n",
+        )
+        .unwrap();
+        let included = serde_yaml::from_str::<Compose>(
+            "services:\n  db:\n    image: mariadb\n  web:\n    image: busybox\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            merge_included(
+                "Service",
+                &mut file.services,
+                included.services,
+                Path::new("other.yaml")
+            )
+            .err()
+            .map(|err| err.to_string()),
+            Some(String::from(
+                "Service \"db\" included from other.yaml conflicts with an existing definition"
+            ))
+        );
+    }
+
+    #[test]
+    fn reset_override() {
+        let config = Config {
+            files: vec![
+                PathBuf::from("tests/fixtures/reset/compose.yaml"),
+                PathBuf::from("tests/fixtures/reset/compose.override.yaml"),
+            ],
             ..Config::default()
         };
+        let file = super::parse(&config, false, &[]).unwrap();
 
-        assert_matches!(super::parse(&config, false), Ok(_));
+        let app = &file.services["app"];
+        assert_eq!(app.ports.len(), 1);
+        assert!(app.volumes.is_empty());
+        assert!(app.healthcheck.is_none());
+        assert_eq!(app.environment.keys().collect::<Vec<_>>(), ["FOO"]);
+    }
+
+    #[test]
+    fn extensions() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/extensions/compose.yaml")],
+            ..Config::default()
+        };
+        let file = super::parse(&config, false, &[]).unwrap();
+
+        let app = &file.services["app"];
+        assert_eq!(app.image.as_deref(), Some("busybox"));
+        assert_eq!(app.labels["tier"], "backend");
+        assert_eq!(app.extensions["x-owner"], "platform");
+
+        let worker = &file.services["worker"];
+        assert_eq!(worker.environment["LEVEL"].as_deref(), Some("worker"));
+
+        let output = serde_yaml::to_value(&file).unwrap();
+        assert_eq!(output["x-common"]["image"], "busybox");
+        assert_eq!(output["services"]["app"]["x-owner"], "platform");
+    }
+
+    #[test]
+    fn extends_cycle() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  a:\n    image: busybox\n    extends: b\n  b:\n    extends: a\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            resolve_extends(&file, None, "a", false, &mut Vec::new())
+                .err()
+                .map(|err| err.to_string()),
+            Some(String::from("Cycles found: a -> b -> a"))
+        );
     }
 
     #[test]
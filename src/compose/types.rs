@@ -1,7 +1,9 @@
 use std::{
+    cell::RefCell,
     convert::Infallible,
     fmt::{self, Display, Formatter},
     hash::{Hash, Hasher},
+    io,
     path::{Path, PathBuf},
     time::Duration,
 };
@@ -11,25 +13,29 @@ use byte_unit::Byte;
 use heck::AsKebabCase;
 use humantime::{format_duration, parse_duration};
 use indexmap::{indexmap, IndexMap, IndexSet};
+use itertools::Itertools;
 use path_absolutize::Absolutize;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_with::{
     formats::PreferMany, serde_as, serde_conv, skip_serializing_none, DefaultOnNull,
     DisplayFromStr, DurationMicroSeconds, OneOrMany, PickFirst, SetLastValueWins,
 };
-use serde_yaml::Value;
+use serde_yaml::{value::TaggedValue, Value};
 
-use crate::utils::{DisplayFromAny, STYLED_WARNING};
+use crate::utils::{Digest, DisplayFromAny, STYLED_WARNING};
 
 #[skip_serializing_none]
 #[serde_as]
 #[serde_with::apply(
-    IndexMap => #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    IndexMap => #[serde(skip_serializing_if = "IndexMap::is_empty", default)],
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub(crate) struct Compose {
     pub(crate) name: Option<String>,
     pub(crate) version: Option<String>,
+    #[serde_as(as = "Vec<PickFirst<(_, IncludeOrString)>>")]
+    pub(crate) include: Vec<Include>,
     #[serde_with(skip_apply)]
     #[serde(default)]
     pub(crate) services: IndexMap<String, Service>,
@@ -38,6 +44,28 @@ pub(crate) struct Compose {
     #[serde_as(as = "IndexMap<_, DefaultOnNull>")]
     pub(crate) volumes: IndexMap<String, Volume>,
     pub(crate) secrets: IndexMap<String, Secret>,
+    pub(crate) configs: IndexMap<String, Config>,
+    #[serde_with(skip_apply)]
+    #[serde(flatten, skip_deserializing)]
+    pub(crate) extensions: IndexMap<String, Value>,
+    #[serde_with(skip_apply)]
+    #[serde(skip)]
+    pub(crate) tags: Vec<(Vec<String>, Tag)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Tag {
+    Reset,
+    Override,
+}
+
+impl Tag {
+    fn as_str(self) -> &'static str {
+        match self {
+            Tag::Reset => "reset",
+            Tag::Override => "override",
+        }
+    }
 }
 
 impl Compose {
@@ -45,7 +73,7 @@ impl Compose {
         Self::default()
     }
 
-    pub(crate) fn merge(&mut self, other: Self) {
+    pub(crate) fn merge(&mut self, other: Self) -> Result<()> {
         if other.version.is_some() {
             self.version = other.version;
         }
@@ -54,19 +82,144 @@ impl Compose {
             self.name = other.name;
         }
 
-        for (name, service) in other.services {
-            self.services
-                .entry(name)
-                .and_modify(|combined_service| combined_service.merge(&service))
-                .or_insert(service);
+        self.include.extend(other.include);
+        self.extensions.extend(other.extensions);
+
+        merge_resources(
+            &mut self.services,
+            other.services,
+            &subtags(&other.tags, "services"),
+        )?;
+        merge_resources(
+            &mut self.networks,
+            other.networks,
+            &subtags(&other.tags, "networks"),
+        )?;
+        merge_resources(
+            &mut self.volumes,
+            other.volumes,
+            &subtags(&other.tags, "volumes"),
+        )?;
+        merge_resources(
+            &mut self.secrets,
+            other.secrets,
+            &subtags(&other.tags, "secrets"),
+        )?;
+        merge_resources(
+            &mut self.configs,
+            other.configs,
+            &subtags(&other.tags, "configs"),
+        )?;
+
+        Ok(())
+    }
+
+    /// Hash of everything a service's containers are created from, including the ID of the
+    /// image they run so that rebuilt or newly pulled images replace existing containers
+    pub(crate) fn config_hash(&self, service_name: &str, image_id: Option<&str>) -> String {
+        let mut service = self.services[service_name].clone();
+        service.scale = None;
+        // Scaling a service adds a deploy section that only sets replicas
+        service.deploy = service
+            .deploy
+            .filter(|deploy| deploy.resources.is_some())
+            .map(|deploy| DeployConfig {
+                replicas: None,
+                ..deploy
+            });
+
+        service.develop = None;
+        service.extensions.clear();
+
+        let networks = service
+            .networks
+            .keys()
+            .filter_map(|network| self.networks.get(network))
+            .collect::<Vec<_>>();
+        let volumes = service
+            .volumes
+            .iter()
+            .filter_map(|volume| match &volume.r#type {
+                ServiceVolumeType::Volume(Some(source)) => self.volumes.get(source),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let secrets = service
+            .secrets
+            .iter()
+            .filter_map(|secret| self.secrets.get(&secret.source))
+            .collect::<Vec<_>>();
+        let configs = service
+            .configs
+            .iter()
+            .filter_map(|config| self.configs.get(&config.source))
+            .collect::<Vec<_>>();
+
+        (&service, networks, volumes, secrets, configs, image_id).digest()
+    }
+
+    pub(crate) fn config_mount(&self, config: &FileReference) -> String {
+        FileReference {
+            source: self.configs[&config.source].name.clone().unwrap(),
+            target: Some(
+                config
+                    .target
+                    .clone()
+                    .unwrap_or_else(|| format!("/{}", config.source)),
+            ),
+            ..config.clone()
+        }
+        .to_string()
+    }
+
+    pub(crate) fn scale(&mut self, scale: &[(String, u32)]) -> Result<()> {
+        for (name, replicas) in scale {
+            let Some(service) = self.services.get_mut(name) else {
+                bail!("No such service: \"{name}\"");
+            };
+
+            if service.container_name.is_some() && *replicas > 1 {
+                bail!(
+                    "Service \"{name}\" cannot scale beyond one container as it has a container name"
+                );
+            }
+
+            service.scale = None;
+            service
+                .deploy
+                .get_or_insert(DeployConfig {
+                    replicas: None,
+                    resources: None,
+                })
+                .replicas = Some(*replicas);
         }
 
-        self.networks = other.networks;
-        self.volumes = other.volumes;
-        self.secrets = other.secrets;
+        Ok(())
+    }
+
+    pub(crate) fn image_name(&self, service_name: &str) -> String {
+        self.services[service_name]
+            .image
+            .clone()
+            .unwrap_or_else(|| format!("{}_{service_name}", self.name.as_ref().unwrap()))
     }
 }
 
+#[skip_serializing_none]
+#[serde_as]
+#[serde_with::apply(
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
+)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Include {
+    #[serde_as(as = "OneOrMany<PickFirst<(AbsPathBuf, DisplayFromAny)>, PreferMany>")]
+    pub(crate) path: Vec<PathBuf>,
+    #[serde_as(as = "Option<PickFirst<(AbsPathBuf, DisplayFromAny)>>")]
+    pub(crate) project_directory: Option<PathBuf>,
+    #[serde_as(as = "OneOrMany<AbsPathBuf, PreferMany>")]
+    pub(crate) env_file: Vec<PathBuf>,
+}
+
 #[skip_serializing_none]
 #[serde_as]
 #[serde_with::apply(
@@ -77,12 +230,16 @@ impl Compose {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub(crate) struct Service {
     pub(crate) blkio_config: Option<BlkioConfig>,
+    #[serde_as(as = "Option<PickFirst<(_, BuildConfigOrString)>>")]
+    pub(crate) build: Option<BuildConfig>,
     pub(crate) cap_add: Vec<String>,
     pub(crate) cap_drop: Vec<String>,
     pub(crate) cgroup: Option<String>,
     pub(crate) cgroup_parent: Option<String>,
     #[serde_as(as = "PickFirst<(_, CommandOrString)>")]
     pub(crate) command: Vec<String>,
+    #[serde_as(as = "SetLastValueWins<PickFirst<(_, FileReferenceOrString)>>")]
+    pub(crate) configs: IndexSet<FileReference>,
     pub(crate) container_name: Option<String>,
     #[serde_as(as = "Option<PickFirst<(DurationMicroSeconds, DurationWithSuffix)>>")]
     pub(crate) cpu_period: Option<Duration>,
@@ -98,6 +255,7 @@ pub(crate) struct Service {
     #[serde_as(as = "PickFirst<(_, IndexMap<DisplayFromAny, _>, DependsOnVec)>")]
     pub(crate) depends_on: IndexMap<String, Dependency>,
     pub(crate) deploy: Option<DeployConfig>,
+    pub(crate) develop: Option<DevelopConfig>,
     pub(crate) device_cgroup_rules: Vec<String>,
     #[serde_as(as = "SetLastValueWins<DeviceOrString>")]
     pub(crate) devices: IndexSet<Device>,
@@ -115,6 +273,8 @@ pub(crate) struct Service {
     )]
     pub(crate) environment: IndexMap<String, Option<String>>,
     pub(crate) expose: Vec<String>,
+    #[serde_as(as = "Option<PickFirst<(_, ExtendsOrString)>>")]
+    pub(crate) extends: Option<Extends>,
     #[serde_as(
         as = "PickFirst<(_, IndexMap<DisplayFromAny, DisplayFromAny>, MappingWithColonEmpty)>"
     )]
@@ -179,6 +339,9 @@ pub(crate) struct Service {
     pub(crate) volumes: IndexSet<ServiceVolume>,
     pub(crate) volumes_from: Vec<String>,
     pub(crate) working_dir: Option<PathBuf>,
+    #[serde_with(skip_apply)]
+    #[serde(flatten, skip_deserializing)]
+    pub(crate) extensions: IndexMap<String, Value>,
 }
 
 fn default_service_networks() -> IndexMap<String, Option<ServiceNetwork>> {
@@ -193,12 +356,22 @@ fn merge(base: &mut Value, other: Value) {
             let base = base.as_mapping_mut().unwrap();
 
             for (key, other_value) in other {
-                base.entry(key.clone())
-                    .and_modify(|value| match key.as_str().unwrap() {
-                        "command" | "entrypoint" => *value = other_value.clone(),
-                        _ => merge(value, other_value.clone()),
-                    })
-                    .or_insert(other_value);
+                match other_value {
+                    Value::Tagged(tagged) if tagged.tag == Tag::Reset.as_str() => {
+                        base.remove(&key);
+                    }
+                    Value::Tagged(tagged) => {
+                        base.insert(key, tagged.value);
+                    }
+                    other_value => {
+                        base.entry(key.clone())
+                            .and_modify(|value| match key.as_str().unwrap() {
+                                "command" | "entrypoint" => *value = other_value.clone(),
+                                _ => merge(value, other_value.clone()),
+                            })
+                            .or_insert(other_value);
+                    }
+                }
             }
         }
         (Value::Sequence(base), Value::Sequence(other)) => {
@@ -208,12 +381,197 @@ fn merge(base: &mut Value, other: Value) {
     }
 }
 
+pub(crate) fn untag(value: &mut Value) -> Result<Vec<(Vec<String>, Tag)>> {
+    let mut tags = Vec::new();
+
+    if let Value::Tagged(tagged) = value {
+        let tag = if tagged.tag == Tag::Reset.as_str() {
+            Tag::Reset
+        } else if tagged.tag == Tag::Override.as_str() {
+            Tag::Override
+        } else {
+            bail!("Unknown tag {}", tagged.tag);
+        };
+
+        tags.push((Vec::new(), tag));
+        *value = tagged.value.clone();
+    }
+
+    match value {
+        Value::Mapping(values) => {
+            for (key, value) in values {
+                let key = key.as_str().map(ToString::to_string).unwrap_or_default();
+
+                for (mut path, tag) in untag(value)? {
+                    path.insert(0, key.clone());
+                    tags.push((path, tag));
+                }
+            }
+        }
+        Value::Sequence(values) => {
+            // Items are appended rather than merged, so there is nothing for a tag to apply to
+            for value in values {
+                if let Some((_, tag)) = untag(value)?.first() {
+                    bail!("Tag !{} is not supported inside sequences", tag.as_str());
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(tags)
+}
+
+fn subtags(tags: &[(Vec<String>, Tag)], key: &str) -> Vec<(Vec<String>, Tag)> {
+    tags.iter()
+        .filter_map(|(path, tag)| match path.split_first() {
+            Some((first, rest)) if first == key => Some((rest.to_vec(), *tag)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn retag(value: &mut Value, tags: &[(Vec<String>, Tag)]) {
+    for (path, tag) in tags.iter().sorted_by_key(|(path, _)| path.len()) {
+        let Some((last, path)) = path.split_last() else {
+            continue;
+        };
+        let mut parent = Some(&mut *value);
+
+        for key in path {
+            parent = parent.and_then(|value| {
+                if let Value::Tagged(_) = value {
+                    None
+                } else {
+                    value.get_mut(key)
+                }
+            });
+        }
+
+        if let Some(Value::Mapping(parent)) = parent {
+            // Empty values are skipped when serializing, which makes them equivalent to a reset
+            let (tag, value) = match parent.get(last) {
+                Some(Value::Tagged(_)) => continue,
+                Some(value) => (*tag, value.clone()),
+                None => (Tag::Reset, Value::Null),
+            };
+
+            parent.insert(
+                Value::String(last.clone()),
+                Value::Tagged(Box::new(TaggedValue {
+                    tag: serde_yaml::value::Tag::new(tag.as_str()),
+                    value,
+                })),
+            );
+        }
+    }
+}
+
+pub(crate) fn extensions(value: &Value) -> IndexMap<String, Value> {
+    value
+        .as_mapping()
+        .into_iter()
+        .flatten()
+        .filter_map(|(key, value)| {
+            key.as_str()
+                .filter(|key| key.starts_with("x-"))
+                .map(|key| (key.to_string(), value.clone()))
+        })
+        .collect()
+}
+
+trait Merge: Serialize + DeserializeOwned {
+    fn from_value(value: Value) -> Result<Self> {
+        Ok(serde_yaml::from_value(value)?)
+    }
+}
+
+impl Merge for Service {
+    fn from_value(value: Value) -> Result<Self> {
+        Ok(Self {
+            extensions: extensions(&value),
+            ..serde_yaml::from_value(value)?
+        })
+    }
+}
+
+impl Merge for Network {}
+
+impl Merge for Volume {}
+
+impl Merge for Secret {}
+
+impl Merge for Config {}
+
+fn merge_resources<T: Merge>(
+    resources: &mut IndexMap<String, T>,
+    other: IndexMap<String, T>,
+    tags: &[(Vec<String>, Tag)],
+) -> Result<()> {
+    if tags.iter().any(|(path, _)| path.is_empty()) {
+        resources.clear();
+    }
+
+    for (name, resource) in other {
+        let tags = subtags(tags, &name);
+
+        match tags.iter().find(|(path, _)| path.is_empty()) {
+            Some((_, Tag::Reset)) => {
+                resources.shift_remove(&name);
+            }
+            Some((_, Tag::Override)) => {
+                resources.insert(name, resource);
+            }
+            None => {
+                if let Some(combined_resource) = resources.get_mut(&name) {
+                    let mut value = serde_yaml::to_value(&*combined_resource)?;
+                    let mut other_value = serde_yaml::to_value(resource)?;
+                    retag(&mut other_value, &tags);
+                    merge(&mut value, other_value);
+                    untag(&mut value)?;
+
+                    *combined_resource = T::from_value(value)?;
+                } else {
+                    resources.insert(name, resource);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
 impl Service {
-    pub(crate) fn merge(&mut self, other: &Self) {
-        let mut value = serde_yaml::to_value(&self).unwrap();
-        merge(&mut value, serde_yaml::to_value(other).unwrap());
+    pub(crate) fn merge(&mut self, other: &Self) -> Result<()> {
+        let mut value = serde_yaml::to_value(&self)?;
+        merge(&mut value, serde_yaml::to_value(other)?);
+
+        *self = Merge::from_value(value)?;
+
+        Ok(())
+    }
 
-        *self = serde_yaml::from_value(value).unwrap();
+    pub(crate) fn replicas(&self) -> u32 {
+        self.deploy
+            .as_ref()
+            .and_then(|deploy| deploy.replicas)
+            .or(self.scale)
+            .unwrap_or(1)
+    }
+
+    pub(crate) fn container_names<'a>(
+        &'a self,
+        project_name: &'a str,
+        service_name: &'a str,
+    ) -> impl Iterator<Item = (u32, String)> + 'a {
+        (1..=self.replicas()).map(move |i| {
+            (
+                i,
+                self.container_name
+                    .clone()
+                    .unwrap_or_else(|| format!("{project_name}_{service_name}_{i}")),
+            )
+        })
     }
 
     pub(crate) fn to_args(&self) -> (Vec<String>, Vec<String>) {
@@ -269,11 +627,11 @@ impl Service {
             args.extend([String::from("--cap-drop"), cap_drop]);
         }
 
-        if let Some(cgroup) = self.cgroup.as_ref().cloned() {
+        if let Some(cgroup) = self.cgroup.clone() {
             args.extend([String::from("--cgroupns"), cgroup]);
         }
 
-        if let Some(cgroup_parent) = self.cgroup_parent.as_ref().cloned() {
+        if let Some(cgroup_parent) = self.cgroup_parent.clone() {
             args.extend([String::from("--cgroup-parent"), cgroup_parent]);
         }
 
@@ -309,7 +667,7 @@ impl Service {
             args.extend([String::from("--cpu-shares"), cpu_shares.to_string()]);
         }
 
-        if let Some(cpuset) = self.cpuset.as_ref().cloned() {
+        if let Some(cpuset) = self.cpuset.clone() {
             args.extend([String::from("--cpuset-cpus"), cpuset]);
         }
 
@@ -465,7 +823,7 @@ impl Service {
             }
         }
 
-        if let Some(hostname) = self.hostname.as_ref().cloned() {
+        if let Some(hostname) = self.hostname.clone() {
             args.extend([String::from("--hostname"), hostname]);
         }
 
@@ -473,7 +831,7 @@ impl Service {
             args.push(String::from("--init"));
         }
 
-        if let Some(ipc) = self.ipc.as_ref().cloned() {
+        if let Some(ipc) = self.ipc.clone() {
             args.extend([String::from("--ipc"), ipc]);
         }
 
@@ -482,7 +840,7 @@ impl Service {
         }
 
         if let Some(logging) = &self.logging {
-            if let Some(driver) = logging.driver.as_ref().cloned() {
+            if let Some(driver) = logging.driver.clone() {
                 args.extend([String::from("--log-driver"), driver]);
             }
 
@@ -502,7 +860,7 @@ impl Service {
             args.extend([String::from("--memory-swap"), memswap_limit.to_string()]);
         }
 
-        if let Some(network_mode) = self.network_mode.as_ref().cloned() {
+        if let Some(network_mode) = self.network_mode.clone() {
             args.extend([String::from("--network"), network_mode]);
         }
 
@@ -514,11 +872,11 @@ impl Service {
             args.extend([String::from("--oom-score-adj"), oom_score_adj.to_string()]);
         }
 
-        if let Some(pid) = self.pid.as_ref().cloned() {
+        if let Some(pid) = self.pid.clone() {
             args.extend([String::from("--pid"), pid]);
         }
 
-        if let Some(platform) = self.platform.as_ref().cloned() {
+        if let Some(platform) = self.platform.clone() {
             args.extend([String::from("--platform"), platform]);
         }
 
@@ -538,7 +896,7 @@ impl Service {
             args.extend([String::from("--restart"), restart.to_string()]);
         }
 
-        if let Some(runtime) = self.runtime.as_ref().cloned() {
+        if let Some(runtime) = self.runtime.clone() {
             global_args.extend([String::from("--runtime"), runtime]);
         }
 
@@ -568,7 +926,7 @@ impl Service {
             ]);
         }
 
-        if let Some(stop_signal) = self.stop_signal.as_ref().cloned() {
+        if let Some(stop_signal) = self.stop_signal.clone() {
             args.extend([String::from("--stop-signal"), stop_signal]);
         }
 
@@ -592,15 +950,15 @@ impl Service {
             args.extend([String::from("--ulimit"), format!("{key}={value}")]);
         }
 
-        if let Some(user) = self.user.as_ref().cloned() {
+        if let Some(user) = self.user.clone() {
             args.extend([String::from("--user"), user]);
         }
 
-        if let Some(userns_mode) = self.userns_mode.as_ref().cloned() {
+        if let Some(userns_mode) = self.userns_mode.clone() {
             args.extend([String::from("--userns"), userns_mode]);
         }
 
-        if let Some(uts) = self.uts.as_ref().cloned() {
+        if let Some(uts) = self.uts.clone() {
             args.extend([String::from("--uts"), uts]);
         }
 
@@ -615,7 +973,7 @@ impl Service {
             ]);
         }
 
-        if let Some(image) = self.image.as_ref().cloned() {
+        if let Some(image) = self.image.clone() {
             args.push(image);
         }
 
@@ -669,12 +1027,111 @@ impl Display for ThrottleDevice {
     }
 }
 
+#[skip_serializing_none]
+#[serde_as]
+#[serde_with::apply(
+    IndexMap => #[serde(skip_serializing_if = "IndexMap::is_empty", default)],
+    IndexSet => #[serde(skip_serializing_if = "IndexSet::is_empty", default)],
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
+)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct BuildConfig {
+    #[serde_as(as = "PickFirst<(AbsPathBuf, DisplayFromAny)>")]
+    #[serde(default = "default_build_context")]
+    pub(crate) context: PathBuf,
+    #[serde_as(as = "Option<DisplayFromAny>")]
+    pub(crate) dockerfile: Option<PathBuf>,
+    #[serde_as(as = "Option<DisplayFromAny>")]
+    pub(crate) dockerfile_inline: Option<String>,
+    #[serde_as(
+        as = "PickFirst<(_, IndexMap<DisplayFromAny, Option<DisplayFromAny>>, MappingWithEqualsNull)>"
+    )]
+    pub(crate) args: IndexMap<String, Option<String>>,
+    #[serde_as(as = "Option<DisplayFromAny>")]
+    pub(crate) target: Option<String>,
+    #[serde_as(
+        as = "PickFirst<(_, IndexMap<DisplayFromAny, DisplayFromAny>, MappingWithEqualsEmpty)>"
+    )]
+    pub(crate) labels: IndexMap<String, String>,
+    #[serde_as(as = "Vec<DisplayFromAny>")]
+    pub(crate) cache_from: Vec<String>,
+    #[serde_as(as = "SetLastValueWins<PickFirst<(_, FileReferenceOrString)>>")]
+    pub(crate) secrets: IndexSet<FileReference>,
+    #[serde_as(as = "PickFirst<(_, IndexMap<DisplayFromAny, DisplayFromAny>, SshVec)>")]
+    pub(crate) ssh: IndexMap<String, String>,
+    #[serde_as(as = "Vec<DisplayFromAny>")]
+    pub(crate) tags: Vec<String>,
+    #[serde_as(as = "Option<DisplayFromAny>")]
+    pub(crate) network: Option<String>,
+    #[serde_as(
+        as = "PickFirst<(_, IndexMap<DisplayFromAny, DisplayFromAny>, MappingWithColonEmpty)>"
+    )]
+    pub(crate) extra_hosts: IndexMap<String, String>,
+}
+
+fn default_build_context() -> PathBuf {
+    absolutize(".").unwrap()
+}
+
+impl BuildConfig {
+    pub(crate) fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        if let Some(dockerfile) = &self.dockerfile {
+            args.extend([
+                String::from("--file"),
+                self.context.join(dockerfile).to_string_lossy().to_string(),
+            ]);
+        }
+
+        if let Some(target) = self.target.clone() {
+            args.extend([String::from("--target"), target]);
+        }
+
+        for (key, value) in &self.labels {
+            args.extend([String::from("--label"), format!("{key}={value}")]);
+        }
+
+        for cache_from in self.cache_from.iter().cloned() {
+            args.extend([String::from("--cache-from"), cache_from]);
+        }
+
+        for (key, value) in &self.ssh {
+            args.extend([
+                String::from("--ssh"),
+                if value.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{key}={value}")
+                },
+            ]);
+        }
+
+        for tag in self.tags.iter().cloned() {
+            args.extend([String::from("--tag"), tag]);
+        }
+
+        if let Some(network) = self.network.clone() {
+            args.extend([String::from("--network"), network]);
+        }
+
+        for (host, ip) in &self.extra_hosts {
+            args.extend([String::from("--add-host"), format!("{host}:{ip}")]);
+        }
+
+        args
+    }
+}
+
+#[skip_serializing_none]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub(crate) struct Dependency {
     pub(crate) condition: Condition,
+    pub(crate) required: Option<bool>,
+    pub(crate) restart: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Debug)]
 pub(crate) enum Condition {
     #[serde(rename = "service_started")]
     Started,
@@ -708,6 +1165,38 @@ pub(crate) struct Resource {
     pub(crate) pids: Option<i32>,
 }
 
+#[serde_with::apply(
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
+)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct DevelopConfig {
+    pub(crate) watch: Vec<WatchRule>,
+}
+
+#[skip_serializing_none]
+#[serde_as]
+#[serde_with::apply(
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
+)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct WatchRule {
+    #[serde_as(as = "AbsPathBuf")]
+    pub(crate) path: PathBuf,
+    pub(crate) action: WatchAction,
+    pub(crate) target: Option<String>,
+    pub(crate) ignore: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub(crate) enum WatchAction {
+    #[serde(rename = "sync")]
+    Sync,
+    #[serde(rename = "rebuild")]
+    Rebuild,
+    #[serde(rename = "sync+restart")]
+    SyncRestart,
+}
+
 #[skip_serializing_none]
 #[serde_as]
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -748,6 +1237,16 @@ impl Display for Device {
     }
 }
 
+#[skip_serializing_none]
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Extends {
+    #[serde_as(as = "DisplayFromAny")]
+    pub(crate) service: String,
+    #[serde_as(as = "Option<PickFirst<(AbsPathBuf, DisplayFromAny)>>")]
+    pub(crate) file: Option<PathBuf>,
+}
+
 #[skip_serializing_none]
 #[serde_as]
 #[serde_with::apply(
@@ -880,6 +1379,7 @@ pub(crate) enum PullPolicy {
     Always,
     Never,
     Missing,
+    Build,
     Newer,
 }
 
@@ -1023,11 +1523,11 @@ impl Display for ServiceVolume {
                 volume.insert(0, source.to_string_lossy().to_string());
 
                 if let Some(bind) = &self.bind {
-                    if let Some(propagation) = bind.propagation.as_ref().cloned() {
+                    if let Some(propagation) = bind.propagation.clone() {
                         options.push(propagation);
                     }
 
-                    if let Some(selinux) = bind.selinux.as_ref().cloned() {
+                    if let Some(selinux) = bind.selinux.clone() {
                         options.push(selinux);
                     }
                 }
@@ -1098,7 +1598,7 @@ pub(crate) struct ServiceVolumeTmpfs {
 #[serde_with::apply(
     IndexMap => #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub(crate) struct Network {
     pub(crate) name: Option<String>,
     pub(crate) driver: Option<String>,
@@ -1118,7 +1618,7 @@ impl Network {
         let mut args = Vec::new();
 
         if !self.external.unwrap_or_default() {
-            if let Some(driver) = self.driver.as_ref().cloned() {
+            if let Some(driver) = self.driver.clone() {
                 args.extend([String::from("--driver"), driver]);
             }
 
@@ -1131,20 +1631,20 @@ impl Network {
             }
 
             if let Some(ipam) = &self.ipam {
-                if let Some(driver) = ipam.driver.as_ref().cloned() {
+                if let Some(driver) = ipam.driver.clone() {
                     args.extend([String::from("--ipam-driver"), driver]);
                 }
 
                 for config in &ipam.config {
-                    if let Some(subnet) = config.subnet.as_ref().cloned() {
+                    if let Some(subnet) = config.subnet.clone() {
                         args.extend([String::from("--subnet"), subnet]);
                     }
 
-                    if let Some(ip_range) = config.ip_range.as_ref().cloned() {
+                    if let Some(ip_range) = config.ip_range.clone() {
                         args.extend([String::from("--ip-range"), ip_range]);
                     }
 
-                    if let Some(gateway) = config.gateway.as_ref().cloned() {
+                    if let Some(gateway) = config.gateway.clone() {
                         args.extend([String::from("--gateway"), gateway]);
                     }
                 }
@@ -1157,8 +1657,6 @@ impl Network {
             for (key, value) in &self.labels {
                 args.extend([String::from("--label"), format!("{key}={value}")]);
             }
-
-            args.push(self.name.clone().unwrap());
         }
 
         args
@@ -1169,14 +1667,14 @@ impl Network {
 #[serde_with::apply(
     Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub(crate) struct IpamConfig {
     pub(crate) driver: Option<String>,
     pub(crate) config: Vec<IpamPool>,
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub(crate) struct IpamPool {
     pub(crate) subnet: Option<String>,
     pub(crate) ip_range: Option<String>,
@@ -1188,7 +1686,7 @@ pub(crate) struct IpamPool {
 #[serde_with::apply(
     IndexMap => #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub(crate) struct Volume {
     pub(crate) name: Option<String>,
     pub(crate) driver: Option<String>,
@@ -1205,7 +1703,7 @@ impl Volume {
         let mut args = Vec::new();
 
         if !self.external.unwrap_or_default() {
-            if let Some(driver) = self.driver.as_ref().cloned() {
+            if let Some(driver) = self.driver.clone() {
                 args.extend([String::from("--driver"), driver]);
             }
 
@@ -1216,8 +1714,6 @@ impl Volume {
             for (key, value) in &self.labels {
                 args.extend([String::from("--label"), format!("{key}={value}")]);
             }
-
-            args.push(self.name.clone().unwrap());
         }
 
         args
@@ -1226,7 +1722,7 @@ impl Volume {
 
 #[skip_serializing_none]
 #[serde_as]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub(crate) struct Secret {
     pub(crate) name: Option<String>,
     #[serde_as(as = "Option<AbsPathBuf>")]
@@ -1237,35 +1733,104 @@ pub(crate) struct Secret {
 
 impl Secret {
     pub(crate) fn to_args(&self) -> Vec<String> {
-        let mut args = Vec::new();
+        if !self.external.unwrap_or_default() && self.environment.is_some() {
+            vec![String::from("--env")]
+        } else {
+            Vec::new()
+        }
+    }
 
-        if !self.external.unwrap_or_default() {
-            if self.environment.is_some() {
-                args.push(String::from("--env"));
-            }
+    pub(crate) fn source(&self) -> Option<String> {
+        if let Some(environment) = self.environment.clone() {
+            Some(environment)
+        } else {
+            self.file
+                .as_ref()
+                .map(|file| file.to_string_lossy().to_string())
+        }
+    }
+}
 
-            args.push(self.name.clone().unwrap());
+#[skip_serializing_none]
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Config {
+    pub(crate) name: Option<String>,
+    #[serde_as(as = "Option<AbsPathBuf>")]
+    pub(crate) file: Option<PathBuf>,
+    pub(crate) environment: Option<String>,
+    pub(crate) content: Option<String>,
+    pub(crate) external: Option<bool>,
+}
 
-            if let Some(environment) = self.environment.as_ref().cloned() {
-                args.push(environment);
-            } else if let Some(file) = &self.file {
-                args.push(file.to_string_lossy().to_string());
-            }
+impl Config {
+    pub(crate) fn to_args(&self) -> Vec<String> {
+        if !self.external.unwrap_or_default() && self.environment.is_some() {
+            vec![String::from("--env")]
+        } else {
+            Vec::new()
         }
+    }
 
-        args
+    pub(crate) fn source(&self) -> Option<String> {
+        if let Some(environment) = self.environment.clone() {
+            Some(environment)
+        } else {
+            self.file
+                .as_ref()
+                .map(|file| file.to_string_lossy().to_string())
+        }
     }
 }
 
+thread_local! {
+    static WORKING_DIRECTORY: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn with_working_directory<T>(directory: &Path, f: impl FnOnce() -> T) -> T {
+    let previous = WORKING_DIRECTORY.replace(Some(directory.to_path_buf()));
+    let result = f();
+    WORKING_DIRECTORY.set(previous);
+
+    result
+}
+
+fn absolutize(path: impl AsRef<Path>) -> io::Result<PathBuf> {
+    WORKING_DIRECTORY.with_borrow(|directory| {
+        if let Some(directory) = directory {
+            path.as_ref().absolutize_from(directory).map(PathBuf::from)
+        } else {
+            path.as_ref().absolutize().map(PathBuf::from)
+        }
+    })
+}
+
 serde_conv!(
     AbsPathBuf,
     PathBuf,
     |path: &PathBuf| path.to_string_lossy().to_string(),
-    |path: String| -> Result<_> {
-        Path::new(&path)
-            .absolutize()
-            .map_err(Error::from)
-            .map(PathBuf::from)
+    |path: String| absolutize(path).map_err(Error::from)
+);
+
+serde_conv!(
+    BuildConfigOrString,
+    BuildConfig,
+    |build: &BuildConfig| build.context.to_string_lossy().to_string(),
+    |context: String| -> Result<_> {
+        Ok(BuildConfig {
+            context: absolutize(context)?,
+            dockerfile: None,
+            dockerfile_inline: None,
+            args: IndexMap::new(),
+            target: None,
+            labels: IndexMap::new(),
+            cache_from: Vec::new(),
+            secrets: IndexSet::new(),
+            ssh: IndexMap::new(),
+            tags: Vec::new(),
+            network: None,
+            extra_hosts: IndexMap::new(),
+        })
     }
 );
 
@@ -1290,6 +1855,8 @@ serde_conv!(
                     dependency,
                     Dependency {
                         condition: Condition::Started,
+                        required: None,
+                        restart: None,
                     },
                 )
             })
@@ -1305,7 +1872,7 @@ serde_conv!(
         let mut parts = device.split(':');
 
         Ok(Device {
-            source: Path::new(parts.next().unwrap()).absolutize()?.to_path_buf(),
+            source: absolutize(parts.next().unwrap())?,
             target: parts.next().map(PathBuf::from),
             permissions: parts.next().map(ToString::to_string),
         })
@@ -1319,6 +1886,18 @@ serde_conv!(
     |duration: String| parse_duration(&duration)
 );
 
+serde_conv!(
+    ExtendsOrString,
+    Extends,
+    |extends: &Extends| extends.service.clone(),
+    |service| -> Result<_, Infallible> {
+        Ok(Extends {
+            service,
+            file: None,
+        })
+    }
+);
+
 serde_conv!(
     FileReferenceOrString,
     FileReference,
@@ -1331,6 +1910,19 @@ serde_conv!(
     }
 );
 
+serde_conv!(
+    IncludeOrString,
+    Include,
+    |include: &Include| include.path[0].to_string_lossy().to_string(),
+    |path: String| -> Result<_> {
+        Ok(Include {
+            path: vec![absolutize(path)?],
+            project_directory: None,
+            env_file: Vec::new(),
+        })
+    }
+);
+
 serde_conv!(
     MappingWithColonEmpty,
     IndexMap<String, String>,
@@ -1528,6 +2120,34 @@ serde_conv!(
     }
 );
 
+serde_conv!(
+    SshVec,
+    IndexMap<String, String>,
+    |ssh: &IndexMap<String, String>| {
+        ssh.iter()
+            .map(|(key, value)| {
+                if value.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{key}={value}")
+                }
+            })
+            .collect::<Vec<_>>()
+    },
+    |ssh: Vec<String>| -> Result<_, Infallible> {
+        Ok(ssh
+            .into_iter()
+            .map(|ssh| {
+                let mut parts = ssh.splitn(2, '=');
+                (
+                    parts.next().unwrap().to_string(),
+                    parts.next().map(ToString::to_string).unwrap_or_default(),
+                )
+            })
+            .collect::<IndexMap<_, _>>())
+    }
+);
+
 pub(crate) fn parse_service_volume(mount: &str) -> Result<ServiceVolume> {
     let mut r#type = ServiceVolumeType::Volume(None);
     let target;
@@ -1543,7 +2163,7 @@ pub(crate) fn parse_service_volume(mount: &str) -> Result<ServiceVolume> {
         }
         [src, dst] if dst.starts_with('/') => {
             if src.starts_with('/') || src.starts_with('.') {
-                r#type = ServiceVolumeType::Bind(Path::new(src).absolutize()?.to_path_buf());
+                r#type = ServiceVolumeType::Bind(absolutize(src)?);
                 bind = Some(ServiceVolumeBind {
                     create_host_path: Some(true),
                     ..ServiceVolumeBind::default()
@@ -1560,7 +2180,7 @@ pub(crate) fn parse_service_volume(mount: &str) -> Result<ServiceVolume> {
         }
         [src, dst, opts] => {
             if src.starts_with('/') || src.starts_with('.') {
-                r#type = ServiceVolumeType::Bind(Path::new(src).absolutize()?.to_path_buf());
+                r#type = ServiceVolumeType::Bind(absolutize(src)?);
                 bind = Some(ServiceVolumeBind {
                     create_host_path: Some(true),
                     ..ServiceVolumeBind::default()
@@ -1644,8 +2264,140 @@ mod tests {
     #[test_resources("tests/fixtures/**/*.y*ml")]
     fn serde(resource: &str) {
         let contents = fs::read_to_string(resource).unwrap();
+        let mut value = serde_yaml::from_str::<Value>(&contents).unwrap();
 
-        assert_matches!(serde_yaml::from_str::<Compose>(&contents), Ok(_));
+        assert_matches!(untag(&mut value), Ok(_));
+        assert_matches!(
+            serde_yaml::from_str::<Compose>(&serde_yaml::to_string(&value).unwrap()),
+            Ok(_)
+        );
+    }
+
+    #[test]
+    fn config_mount() {
+        let contents = fs::read_to_string("tests/fixtures/configs/compose.yaml").unwrap();
+        let mut file = serde_yaml::from_str::<Compose>(&contents).unwrap();
+
+        for (name, config) in &mut file.configs {
+            config.name = Some(format!("project_{name}"));
+        }
+
+        assert_eq!(
+            file.services["app"]
+                .configs
+                .iter()
+                .map(|config| file.config_mount(config))
+                .collect::<Vec<_>>(),
+            [
+                "project_app,target=/app",
+                "project_greeting,target=/etc/greeting,uid=1000,gid=1000,mode=440",
+                "project_version,target=/etc/version",
+            ]
+        );
+    }
+
+    #[test]
+    fn scale() {
+        let contents = fs::read_to_string("tests/fixtures/scale/compose.yaml").unwrap();
+        let mut file = serde_yaml::from_str::<Compose>(&contents).unwrap();
+
+        assert_eq!(file.services["web"].replicas(), 2);
+        assert_eq!(file.services["worker"].replicas(), 3);
+        assert_eq!(file.services["db"].replicas(), 1);
+
+        file.scale(&[(String::from("web"), 3), (String::from("worker"), 0)])
+            .unwrap();
+
+        assert_eq!(
+            file.services["web"]
+                .container_names("project", "web")
+                .collect::<Vec<_>>(),
+            [
+                (1, String::from("project_web_1")),
+                (2, String::from("project_web_2")),
+                (3, String::from("project_web_3")),
+            ]
+        );
+        assert_eq!(
+            file.services["worker"]
+                .container_names("project", "worker")
+                .count(),
+            0
+        );
+        assert_eq!(
+            file.services["db"]
+                .container_names("project", "db")
+                .collect::<Vec<_>>(),
+            [(1, String::from("database"))]
+        );
+        assert_matches!(file.scale(&[(String::from("db"), 2)]), Err(_));
+        assert_matches!(file.scale(&[(String::from("cache"), 1)]), Err(_));
+    }
+
+    #[test]
+    fn build_args() {
+        let contents = fs::read_to_string("tests/fixtures/build/compose.yaml").unwrap();
+        let file = serde_yaml::from_str::<Compose>(&contents).unwrap();
+        let build = file.services["full"].build.as_ref().unwrap();
+
+        assert_eq!(
+            build.to_args(),
+            [
+                "--file",
+                &Path::new("Dockerfile")
+                    .absolutize()
+                    .unwrap()
+                    .to_string_lossy(),
+                "--target",
+                "final",
+                "--label",
+                "com.example.description=Full build",
+                "--cache-from",
+                "build-test/full:cache",
+                "--ssh",
+                "default",
+                "--tag",
+                "build-test/full:extra",
+                "--network",
+                "host",
+                "--add-host",
+                "somehost:162.242.195.82",
+            ]
+        );
+    }
+
+    #[test]
+    fn config_hash() {
+        let contents = fs::read_to_string("tests/fixtures/dependencies/compose.yaml").unwrap();
+        let file = serde_yaml::from_str::<Compose>(&contents).unwrap();
+
+        let mut other = serde_yaml::from_str::<Compose>(&contents).unwrap();
+        other.services["foo"]
+            .environment
+            .insert(String::from("FOO"), Some(String::from("bar")));
+        other.services["bar"].scale = Some(2);
+        other.services["bar"]
+            .extensions
+            .insert(String::from("x-note"), Value::from("ignored"));
+
+        assert_ne!(
+            file.config_hash("foo", None),
+            other.config_hash("foo", None)
+        );
+        assert_eq!(
+            file.config_hash("bar", None),
+            other.config_hash("bar", None)
+        );
+        let mut scaled = file.clone();
+        scaled.scale(&[(String::from("bar"), 3)]).unwrap();
+        assert_eq!(
+            file.config_hash("bar", None),
+            scaled.config_hash("bar", None)
+        );
+        assert_ne!(
+            file.config_hash("bar", Some("sha256:1")),
+            file.config_hash("bar", Some("sha256:2"))
+        );
     }
 
     #[test]
@@ -1654,7 +2406,7 @@ mod tests {
         let other = fs::read_to_string("tests/fixtures/override/compose.override.yaml").unwrap();
 
         let mut result = serde_yaml::from_str::<Compose>(&base).unwrap();
-        result.merge(serde_yaml::from_str(&other).unwrap());
+        result.merge(serde_yaml::from_str(&other).unwrap()).unwrap();
 
         let expected = fs::read_to_string("tests/fixtures/override/compose.expected.yaml").unwrap();
 
@@ -1663,4 +2415,80 @@ mod tests {
             format!("{:#?}", serde_yaml::from_str::<Compose>(&expected).unwrap())
         );
     }
+
+    #[test]
+    fn merge_tags() {
+        let base = fs::read_to_string("tests/fixtures/reset/compose.yaml").unwrap();
+        let other = fs::read_to_string("tests/fixtures/reset/compose.override.yaml").unwrap();
+
+        let mut other = serde_yaml::from_str::<Value>(&other).unwrap();
+        let tags = untag(&mut other).unwrap();
+
+        let mut result = serde_yaml::from_str::<Compose>(&base).unwrap();
+        result
+            .merge(Compose {
+                tags,
+                ..serde_yaml::from_str(&serde_yaml::to_string(&other).unwrap()).unwrap()
+            })
+            .unwrap();
+
+        let expected = fs::read_to_string("tests/fixtures/reset/compose.expected.yaml").unwrap();
+
+        assert_eq!(
+            format!("{result:#?}"),
+            format!("{:#?}", serde_yaml::from_str::<Compose>(&expected).unwrap())
+        );
+    }
+
+    #[test]
+    fn untag_sequence() {
+        let mut value = serde_yaml::from_str::<Value>(
+            "
+            services:
+              app:
+                ports:
+                  - !reset 8080:80
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            untag(&mut value).unwrap_err().to_string(),
+            "Tag !reset is not supported inside sequences"
+        );
+    }
+
+    #[test]
+    fn merge_invalid() {
+        let mut result = serde_yaml::from_str::<Compose>(
+            "
+            services:
+              app:
+                extends:
+                  service: base
+              base:
+                image: busybox
+            ",
+        )
+        .unwrap();
+        let mut other = serde_yaml::from_str::<Value>(
+            "
+            services:
+              app:
+                extends:
+                  service: !reset base
+            ",
+        )
+        .unwrap();
+        let tags = untag(&mut other).unwrap();
+
+        let err = result
+            .merge(Compose {
+                tags,
+                ..serde_yaml::from_value(other).unwrap()
+            })
+            .unwrap_err();
+
+        assert!(err.to_string().contains("missing field `service`"));
+    }
 }
@@ -28,6 +28,7 @@ pub(crate) struct Config {
     pub(crate) env_file: PathBuf,
     pub(crate) project_directory: PathBuf,
     pub(crate) dry_run: bool,
+    pub(crate) query_socket: bool,
 }
 
 fn find(directory: &Path, files: &[String]) -> Result<PathBuf> {
@@ -105,6 +106,7 @@ fn resolve(flags: &Flags) -> Result<Config> {
         profiles: flags.profile.unwrap_or_default(),
         project_directory,
         dry_run: flags.dry_run.unwrap_or_default(),
+        query_socket: flags.query_socket.unwrap_or_default(),
         ..Config::default()
     })
 }
@@ -1,6 +1,6 @@
 automod::dir!("src");
 
-use std::{env, path::PathBuf};
+use std::{env, path::PathBuf, process};
 
 use anyhow::Result;
 use clap::{ArgAction, Parser};
@@ -54,6 +54,10 @@ pub(crate) struct Flags {
     /// Only show the Podman commands that will be executed
     #[arg(long, action = ArgAction::SetTrue, global = true)]
     pub(crate) dry_run: Option<bool>,
+
+    /// Send read-only queries to Podman's REST API socket, changes are still made with the CLI
+    #[arg(long, action = ArgAction::SetTrue, global = true)]
+    pub(crate) query_socket: Option<bool>,
 }
 
 #[tokio::main]
@@ -62,5 +66,11 @@ async fn main() -> Result<()> {
     let config = config::load(args.flags)?;
 
     env::set_current_dir(&config.project_directory)?;
-    commands::run(args.command, config).await
+    let code = commands::run(args.command, config).await?;
+
+    if code != 0 {
+        process::exit(code);
+    }
+
+    Ok(())
 }
@@ -1,46 +1,178 @@
 automod::dir!(pub(crate) "src/podman");
 
 use std::{
-    ffi::OsStr,
-    path::PathBuf,
+    ffi::{OsStr, OsString},
+    future::Future,
     pin::Pin,
-    process::{self, Stdio},
 };
 
-use anyhow::{anyhow, bail, Context, Error, Result};
-use futures::{
-    stream::{self, select},
-    Stream, StreamExt, TryStreamExt,
-};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use futures::{stream, Stream, StreamExt};
 use once_cell::sync::Lazy;
-use tokio::{
-    io::{AsyncBufReadExt, BufReader},
-    process::Command,
-};
-use tokio_stream::wrappers::LinesStream;
 
-use self::types::Version;
+use self::{
+    cli::Cli,
+    types::{Container, ContainerState, Network, Secret, Volume},
+};
 use crate::config::Config;
 
 static PODMAN_MIN_SUPPORTED_VERSION: Lazy<semver::Version> =
     Lazy::new(|| semver::Version::new(4, 3, 0));
 
+/// Pod created with `podman pod create`
+#[derive(Debug)]
+pub(crate) struct PodSpec {
+    pub(crate) name: String,
+    pub(crate) labels: Vec<String>,
+}
+
+/// Container created with `podman create`
+#[derive(Debug)]
+pub(crate) struct ContainerSpec {
+    pub(crate) name: String,
+    pub(crate) pod: String,
+    pub(crate) image: String,
+    pub(crate) command: Vec<String>,
+    pub(crate) requires: Vec<String>,
+    pub(crate) labels: Vec<String>,
+    /// Arguments that come before the subcommand, such as `--runtime`
+    pub(crate) global_args: Vec<String>,
+    /// Any other `podman create` options
+    pub(crate) options: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ResourceKind {
+    Network,
+    Volume,
+    Secret,
+}
+
+impl ResourceKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Network => "network",
+            Self::Volume => "volume",
+            Self::Secret => "secret",
+        }
+    }
+}
+
+/// Policy `podman pull` uses to decide whether to replace a local image
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum PullPolicy {
+    Always,
+    Newer,
+}
+
+impl PullPolicy {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::Newer => "newer",
+        }
+    }
+}
+
+/// Network, volume or secret created with `podman network|volume|secret create`
+#[derive(Debug)]
+pub(crate) struct ResourceSpec {
+    pub(crate) kind: ResourceKind,
+    pub(crate) name: String,
+    pub(crate) labels: Vec<String>,
+    pub(crate) options: Vec<String>,
+    /// Data of a secret, either a file or an environment variable
+    pub(crate) source: Option<String>,
+}
+
+/// Operations the commands perform against a container engine
+#[async_trait(?Send)]
+pub(crate) trait ContainerEngine {
+    async fn version(&self) -> Result<semver::Version>;
+
+    /// Run a subcommand that has no dedicated operation
+    async fn run(&self, args: Vec<OsString>) -> Result<String>;
+
+    fn watch(&self, args: Vec<OsString>) -> Result<Pin<Box<dyn Stream<Item = Result<String>>>>>;
+
+    async fn attach(&self, args: Vec<OsString>) -> Result<()>;
+
+    /// Check whether a pod, container, network, volume or image exists
+    async fn exists(&self, kind: &str, name: &str) -> Result<bool>;
+
+    async fn containers(&self, filters: Vec<String>) -> Result<Vec<Container>>;
+
+    async fn networks(&self, filters: Vec<String>) -> Result<Vec<Network>>;
+
+    async fn volumes(&self, filters: Vec<String>) -> Result<Vec<Volume>>;
+
+    async fn secrets(&self) -> Result<Vec<Secret>>;
+
+    async fn inspect_container(&self, name: &str) -> Result<ContainerState>;
+
+    /// ID of a local image, if it exists
+    async fn image_id(&self, image: &str) -> Result<Option<String>>;
+
+    async fn create_pod(&self, pod: &PodSpec) -> Result<()>;
+
+    /// Remove a pod, ignoring pods that do not exist
+    async fn remove_pod(&self, name: &str) -> Result<()>;
+
+    async fn create_container(&self, container: &ContainerSpec) -> Result<()>;
+
+    async fn start_container(&self, name: &str) -> Result<()>;
+
+    async fn stop_container(&self, name: &str, timeout: Option<u32>) -> Result<()>;
+
+    /// Remove a container and the containers that depend on it, ignoring containers that do not
+    /// exist
+    async fn remove_container(&self, name: &str, force: bool, volumes: bool) -> Result<()>;
+
+    /// Wait for a container to exit and return its exit code
+    async fn wait_container(&self, name: &str) -> Result<i32>;
+
+    async fn create_resource(&self, resource: &ResourceSpec) -> Result<()>;
+
+    async fn remove_resource(&self, kind: ResourceKind, name: &str) -> Result<()>;
+
+    async fn pull_image(&self, image: &str, policy: PullPolicy, quiet: bool) -> Result<()>;
+
+    async fn push_image(&self, image: &str) -> Result<()>;
+
+    async fn remove_image(&self, image: &str) -> Result<()>;
+}
+
 pub(crate) struct Podman {
-    project_directory: PathBuf,
+    engine: Box<dyn ContainerEngine>,
     dry_run: bool,
 }
 
+fn collect_args<I, S>(args: I) -> Vec<OsString>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    args.into_iter()
+        .map(|arg| arg.as_ref().to_os_string())
+        .collect()
+}
+
+fn collect_filters<I, S>(filters: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    filters
+        .into_iter()
+        .map(|filter| filter.as_ref().to_owned())
+        .collect()
+}
+
 impl Podman {
     pub(crate) async fn new(config: &Config) -> Result<Self> {
-        let podman = Self {
-            project_directory: config.project_directory.clone(),
-            dry_run: config.dry_run,
-        };
-        let output = podman.force_run(["version", "--format", "json"]).await?;
-        let version = serde_json::from_str::<Version>(&output)
-            .with_context(|| anyhow!("Podman version not recognised"))?
-            .client
-            .version;
+        let podman = Self::with_engine(Cli::new(config)?, config.dry_run);
+        let version = podman.engine.version().await?;
 
         if version < *PODMAN_MIN_SUPPORTED_VERSION {
             bail!(
@@ -52,15 +184,32 @@ impl Podman {
         Ok(podman)
     }
 
-    fn command<I, S>(&self, args: I) -> Command
+    pub(crate) fn with_engine<E>(engine: E, dry_run: bool) -> Self
+    where
+        E: ContainerEngine + 'static,
+    {
+        Self {
+            engine: Box::new(engine),
+            dry_run,
+        }
+    }
+
+    pub(crate) fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    fn print<I, S>(args: I)
     where
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
-        let mut command = Command::new("podman");
-        command.current_dir(&self.project_directory).args(args);
-
-        command
+        println!(
+            "`podman {}`",
+            shell_words::join(
+                args.into_iter()
+                    .map(|arg| arg.as_ref().to_string_lossy().to_string())
+            )
+        );
     }
 
     pub(crate) async fn run<I, S>(&self, args: I) -> Result<String>
@@ -69,13 +218,7 @@ impl Podman {
         S: AsRef<OsStr>,
     {
         if self.dry_run {
-            println!(
-                "`podman {}`",
-                shell_words::join(
-                    args.into_iter()
-                        .map(|arg| arg.as_ref().to_string_lossy().to_string())
-                )
-            );
+            Self::print(args);
 
             Ok(String::new())
         } else {
@@ -88,37 +231,7 @@ impl Podman {
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
-        let mut command = self.command(args);
-
-        let output = command.output().await.with_context(|| {
-            anyhow!(
-                "`{} {}` cannot be executed",
-                command.as_std().get_program().to_string_lossy(),
-                shell_words::join(
-                    command
-                        .as_std()
-                        .get_args()
-                        .map(|arg| arg.to_string_lossy().to_string())
-                )
-            )
-        })?;
-
-        if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
-        } else {
-            Err(
-                anyhow!("{}", String::from_utf8_lossy(&output.stderr)).context(anyhow!(
-                    "`{} {}` returned an error",
-                    command.as_std().get_program().to_string_lossy(),
-                    shell_words::join(
-                        command
-                            .as_std()
-                            .get_args()
-                            .map(|arg| arg.to_string_lossy().to_string())
-                    )
-                )),
-            )
-        }
+        self.engine.run(collect_args(args)).await
     }
 
     pub(crate) fn watch<I, S>(&self, args: I) -> Result<Pin<Box<dyn Stream<Item = Result<String>>>>>
@@ -127,28 +240,11 @@ impl Podman {
         S: AsRef<OsStr>,
     {
         if self.dry_run {
-            println!(
-                "`podman {}`",
-                shell_words::join(
-                    args.into_iter()
-                        .map(|arg| arg.as_ref().to_string_lossy().to_string())
-                )
-            );
+            Self::print(args);
 
             Ok(stream::empty().boxed())
         } else {
-            let child = self
-                .command(args)
-                .stdin(Stdio::null())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()?;
-            let stdout = BufReader::new(child.stdout.unwrap()).lines();
-            let stderr = BufReader::new(child.stderr.unwrap()).lines();
-
-            Ok(select(LinesStream::new(stdout), LinesStream::new(stderr))
-                .map_err(Error::from)
-                .boxed())
+            self.engine.watch(collect_args(args))
         }
     }
 
@@ -158,23 +254,163 @@ impl Podman {
         S: AsRef<OsStr>,
     {
         if self.dry_run {
-            println!(
-                "`podman {}`",
-                shell_words::join(
-                    args.into_iter()
-                        .map(|arg| arg.as_ref().to_string_lossy().to_string())
-                )
-            );
+            Self::print(args);
 
             Ok(())
         } else {
-            let status = self.command(args).spawn()?.wait().await?;
+            self.engine.attach(collect_args(args)).await
+        }
+    }
 
-            if !status.success() {
-                process::exit(status.code().unwrap_or(1));
-            }
+    async fn mutate<F>(&self, args: Vec<String>, operation: F) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        if self.dry_run {
+            Self::print(args);
 
             Ok(())
+        } else {
+            operation.await
         }
     }
+
+    pub(crate) async fn exists(&self, kind: &str, name: &str) -> Result<bool> {
+        self.engine.exists(kind, name).await
+    }
+
+    pub(crate) async fn containers<I, S>(&self, filters: I) -> Result<Vec<Container>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.engine.containers(collect_filters(filters)).await
+    }
+
+    pub(crate) async fn networks<I, S>(&self, filters: I) -> Result<Vec<Network>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.engine.networks(collect_filters(filters)).await
+    }
+
+    pub(crate) async fn volumes<I, S>(&self, filters: I) -> Result<Vec<Volume>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.engine.volumes(collect_filters(filters)).await
+    }
+
+    pub(crate) async fn secrets(&self) -> Result<Vec<Secret>> {
+        self.engine.secrets().await
+    }
+
+    pub(crate) async fn inspect_container(&self, name: &str) -> Result<ContainerState> {
+        self.engine.inspect_container(name).await
+    }
+
+    pub(crate) async fn image_id(&self, image: &str) -> Result<Option<String>> {
+        self.engine.image_id(image).await
+    }
+
+    pub(crate) async fn create_pod(&self, pod: &PodSpec) -> Result<()> {
+        self.mutate(cli::create_pod_args(pod), self.engine.create_pod(pod))
+            .await
+    }
+
+    pub(crate) async fn remove_pod(&self, name: &str) -> Result<()> {
+        self.mutate(cli::remove_pod_args(name), self.engine.remove_pod(name))
+            .await
+    }
+
+    pub(crate) async fn create_container(&self, container: &ContainerSpec) -> Result<()> {
+        self.mutate(
+            cli::create_container_args(container),
+            self.engine.create_container(container),
+        )
+        .await
+    }
+
+    pub(crate) async fn start_container(&self, name: &str) -> Result<()> {
+        self.mutate(
+            cli::start_container_args(name),
+            self.engine.start_container(name),
+        )
+        .await
+    }
+
+    pub(crate) async fn stop_container(&self, name: &str, timeout: Option<u32>) -> Result<()> {
+        self.mutate(
+            cli::stop_container_args(name, timeout),
+            self.engine.stop_container(name, timeout),
+        )
+        .await
+    }
+
+    pub(crate) async fn remove_container(
+        &self,
+        name: &str,
+        force: bool,
+        volumes: bool,
+    ) -> Result<()> {
+        self.mutate(
+            cli::remove_container_args(name, force, volumes),
+            self.engine.remove_container(name, force, volumes),
+        )
+        .await
+    }
+
+    pub(crate) async fn wait_container(&self, name: &str) -> Result<i32> {
+        if self.dry_run {
+            Self::print(cli::wait_container_args(name));
+
+            Ok(0)
+        } else {
+            self.engine.wait_container(name).await
+        }
+    }
+
+    pub(crate) async fn create_resource(&self, resource: &ResourceSpec) -> Result<()> {
+        self.mutate(
+            cli::create_resource_args(resource),
+            self.engine.create_resource(resource),
+        )
+        .await
+    }
+
+    pub(crate) async fn remove_resource(&self, kind: ResourceKind, name: &str) -> Result<()> {
+        self.mutate(
+            cli::remove_resource_args(kind, name),
+            self.engine.remove_resource(kind, name),
+        )
+        .await
+    }
+
+    pub(crate) async fn pull_image(
+        &self,
+        image: &str,
+        policy: PullPolicy,
+        quiet: bool,
+    ) -> Result<()> {
+        self.mutate(
+            cli::pull_image_args(image, policy, quiet),
+            self.engine.pull_image(image, policy, quiet),
+        )
+        .await
+    }
+
+    pub(crate) async fn push_image(&self, image: &str) -> Result<()> {
+        self.mutate(cli::push_image_args(image), self.engine.push_image(image))
+            .await
+    }
+
+    pub(crate) async fn remove_image(&self, image: &str) -> Result<()> {
+        self.mutate(
+            cli::remove_image_args(image),
+            self.engine.remove_image(image),
+        )
+        .await
+    }
 }
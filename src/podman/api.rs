@@ -0,0 +1,367 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    str,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use indexmap::IndexMap;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+const PATH: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/')
+    .remove(b':');
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    message: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct Client {
+    socket: PathBuf,
+}
+
+impl Client {
+    pub(crate) fn new(socket: PathBuf) -> Self {
+        Self { socket }
+    }
+
+    pub(crate) fn from_env() -> Result<Self> {
+        let socket = if let Ok(host) = env::var("CONTAINER_HOST") {
+            if let Some(path) = host.strip_prefix("unix://") {
+                PathBuf::from(path)
+            } else {
+                bail!("Only Unix sockets are supported: {host} found");
+            }
+        } else if let Ok(runtime_dir) = env::var("XDG_RUNTIME_DIR") {
+            Path::new(&runtime_dir).join("podman/podman.sock")
+        } else {
+            PathBuf::from("/run/podman/podman.sock")
+        };
+
+        Ok(Self::new(socket))
+    }
+
+    async fn request(&self, path: &str) -> Result<(u16, Vec<u8>)> {
+        let mut stream = UnixStream::connect(&self.socket)
+            .await
+            .with_context(|| anyhow!("{} cannot be connected to", self.socket.display()))?;
+
+        stream
+            .write_all(
+                format!(
+                    "GET /v4.3.0/libpod{path} HTTP/1.1\r\nHost: d\r\nConnection: close\r\n\r\n"
+                )
+                .as_bytes(),
+            )
+            .await?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+
+        let (head, body) = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .map(|position| (&response[..position], &response[position + 4..]))
+            .ok_or_else(|| anyhow!("Malformed response"))?;
+        let mut lines = str::from_utf8(head)?.split("\r\n");
+        let status = lines
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| anyhow!("Malformed response"))?;
+        let chunked = lines.any(|line| {
+            line.split_once(':').is_some_and(|(key, value)| {
+                key.eq_ignore_ascii_case("transfer-encoding")
+                    && value.trim().eq_ignore_ascii_case("chunked")
+            })
+        });
+
+        Ok((
+            status,
+            if chunked {
+                dechunk(body)?
+            } else {
+                body.to_vec()
+            },
+        ))
+    }
+
+    pub(crate) async fn get<T>(&self, path: &str) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let (status, body) = self.request(path).await?;
+
+        if (200..300).contains(&status) {
+            Ok(serde_json::from_slice(&body)?)
+        } else {
+            Err(error(&body)).context(anyhow!("`GET {path}` returned an error"))
+        }
+    }
+
+    pub(crate) async fn exists(&self, path: &str) -> Result<bool> {
+        let (status, body) = self.request(path).await?;
+
+        match status {
+            204 => Ok(true),
+            404 => Ok(false),
+            _ => Err(error(&body)).context(anyhow!("`GET {path}` returned an error")),
+        }
+    }
+}
+
+fn dechunk(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+
+    loop {
+        let position = body
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(|| anyhow!("Malformed chunk"))?;
+        let size = str::from_utf8(&body[..position])?;
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or(size).trim(), 16)?;
+
+        if size == 0 {
+            return Ok(content);
+        }
+
+        content.extend_from_slice(
+            body.get(position + 2..position + 2 + size)
+                .ok_or_else(|| anyhow!("Malformed chunk"))?,
+        );
+        body = body
+            .get(position + 4 + size..)
+            .ok_or_else(|| anyhow!("Malformed chunk"))?;
+    }
+}
+
+fn error(body: &[u8]) -> anyhow::Error {
+    serde_json::from_slice::<ErrorResponse>(body).map_or_else(
+        |_| anyhow!("{}", String::from_utf8_lossy(body)),
+        |response| anyhow!("{}", response.message),
+    )
+}
+
+pub(crate) fn path(segment: &str) -> String {
+    utf8_percent_encode(segment, PATH).to_string()
+}
+
+pub(crate) fn filters<I, S>(filters: I) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut map = IndexMap::<_, Vec<_>>::new();
+
+    for filter in filters {
+        let (key, value) = filter
+            .as_ref()
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid filter: {}", filter.as_ref()))?;
+        map.entry(key.to_owned())
+            .or_default()
+            .push(value.to_owned());
+    }
+
+    Ok(utf8_percent_encode(&serde_json::to_string(&map)?, NON_ALPHANUMERIC).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use pretty_assertions::assert_eq;
+    use tokio::{
+        io::AsyncBufReadExt, io::BufReader, net::UnixListener, sync::Mutex, task::JoinHandle,
+    };
+
+    use super::*;
+    use crate::podman::types::Container;
+
+    struct Server {
+        socket: PathBuf,
+        requests: Arc<Mutex<Vec<String>>>,
+        handle: JoinHandle<()>,
+    }
+
+    impl Server {
+        fn new(responses: Vec<&'static str>) -> Self {
+            let socket = env::temp_dir().join(format!("haddock-{}.sock", fastrand::u64(..)));
+            let listener = UnixListener::bind(&socket).unwrap();
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let handle = tokio::spawn({
+                let requests = requests.clone();
+
+                async move {
+                    for response in responses {
+                        let (mut stream, _) = listener.accept().await.unwrap();
+                        let (reader, mut writer) = stream.split();
+                        let mut reader = BufReader::new(reader);
+                        let mut line = String::new();
+
+                        reader.read_line(&mut line).await.unwrap();
+                        requests.lock().await.push(line.trim_end().to_owned());
+
+                        while line != "\r\n" {
+                            line.clear();
+                            reader.read_line(&mut line).await.unwrap();
+                        }
+
+                        writer.write_all(response.as_bytes()).await.unwrap();
+                    }
+                }
+            });
+
+            Self {
+                socket,
+                requests,
+                handle,
+            }
+        }
+
+        fn client(&self) -> Client {
+            Client::new(self.socket.clone())
+        }
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            self.handle.abort();
+            std::fs::remove_file(&self.socket).ok();
+        }
+    }
+
+    #[tokio::test]
+    async fn containers() {
+        let server = Server::new(vec![concat!(
+            "HTTP/1.1 200 OK\r\n",
+            "Content-Type: application/json\r\n",
+            "Transfer-Encoding: chunked\r\n",
+            "\r\n",
+            "2d\r\n",
+            r#"[{"Id":"abc","Labels":{"io.podman.compose.ser"#,
+            "\r\n",
+            "3a\r\n",
+            r#"vice":"web"},"Names":["project-web-1"],"State":"running"}]"#,
+            "\r\n",
+            "0\r\n",
+            "\r\n",
+        )]);
+        let containers = server
+            .client()
+            .get::<Vec<Container>>(&format!(
+                "/containers/json?all=true&filters={}",
+                filters(["label=io.podman.compose.oneoff=false", "pod=project"]).unwrap()
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].id, "abc");
+        assert_eq!(containers[0].names, ["project-web-1"]);
+        assert_eq!(
+            containers[0]
+                .labels
+                .as_ref()
+                .and_then(|labels| labels.service.as_deref()),
+            Some("web")
+        );
+        assert_eq!(
+            *server.requests.lock().await,
+            [concat!(
+                "GET /v4.3.0/libpod/containers/json?all=true&filters=",
+                "%7B%22label%22%3A%5B%22io%2Epodman%2Ecompose%2Eoneoff%3Dfalse%22%5D%2C",
+                "%22pod%22%3A%5B%22project%22%5D%7D HTTP/1.1"
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn exists() {
+        let server = Server::new(vec![
+            "HTTP/1.1 204 No Content\r\n\r\n",
+            concat!(
+                "HTTP/1.1 404 Not Found\r\n",
+                "Content-Length: 36\r\n",
+                "\r\n",
+                r#"{"message":"no such pod","cause":""}"#
+            ),
+        ]);
+        let client = server.client();
+
+        assert!(client
+            .exists(&format!(
+                "/images/{}/exists",
+                path("docker.io/library/nginx:latest")
+            ))
+            .await
+            .unwrap());
+        assert!(!client.exists("/pods/project/exists").await.unwrap());
+        assert_eq!(
+            *server.requests.lock().await,
+            [
+                "GET /v4.3.0/libpod/images/docker.io/library/nginx:latest/exists HTTP/1.1",
+                "GET /v4.3.0/libpod/pods/project/exists HTTP/1.1"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn error() {
+        let server = Server::new(vec![concat!(
+            "HTTP/1.1 500 Internal Server Error\r\n",
+            "Content-Length: 45\r\n",
+            "\r\n",
+            r#"{"message":"something went wrong","cause":""}"#
+        )]);
+        let err = server
+            .client()
+            .get::<Vec<Container>>("/containers/json")
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.root_cause().to_string(), "something went wrong");
+    }
+
+    #[test]
+    fn socket() {
+        temp_env::with_vars(
+            [
+                ("CONTAINER_HOST", Some("unix:///tmp/podman.sock")),
+                ("XDG_RUNTIME_DIR", Some("/run/user/1000")),
+            ],
+            || {
+                assert_eq!(
+                    Client::from_env().unwrap().socket,
+                    Path::new("/tmp/podman.sock")
+                );
+            },
+        );
+        temp_env::with_vars(
+            [
+                ("CONTAINER_HOST", None),
+                ("XDG_RUNTIME_DIR", Some("/run/user/1000")),
+            ],
+            || {
+                assert_eq!(
+                    Client::from_env().unwrap().socket,
+                    Path::new("/run/user/1000/podman/podman.sock")
+                );
+            },
+        );
+        temp_env::with_var("CONTAINER_HOST", Some("ssh://core@localhost"), || {
+            assert!(Client::from_env().is_err());
+        });
+    }
+}
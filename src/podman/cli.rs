@@ -0,0 +1,440 @@
+use std::{
+    ffi::{OsStr, OsString},
+    path::PathBuf,
+    pin::Pin,
+    process::{self, Stdio},
+};
+
+use anyhow::{anyhow, Context, Error, Result};
+use async_trait::async_trait;
+use futures::{stream::select, Stream, StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+};
+use tokio_stream::wrappers::LinesStream;
+
+use super::{
+    api::{self, Client},
+    types::{
+        Container, ContainerInspect, ContainerState, ImageInspect, Network, Secret, Version,
+        VersionComponent, Volume,
+    },
+    ContainerEngine, ContainerSpec, PodSpec, PullPolicy, ResourceKind, ResourceSpec,
+};
+use crate::config::Config;
+
+fn labels(labels: &[String]) -> impl Iterator<Item = String> + '_ {
+    labels
+        .iter()
+        .flat_map(|label| [String::from("--label"), label.clone()])
+}
+
+pub(crate) fn create_pod_args(pod: &PodSpec) -> Vec<String> {
+    ["pod", "create", "--share", "none"]
+        .into_iter()
+        .map(String::from)
+        .chain(labels(&pod.labels))
+        .chain([pod.name.clone()])
+        .collect()
+}
+
+pub(crate) fn remove_pod_args(name: &str) -> Vec<String> {
+    ["pod", "rm", "--ignore", name]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+pub(crate) fn create_container_args(container: &ContainerSpec) -> Vec<String> {
+    container
+        .global_args
+        .iter()
+        .cloned()
+        .chain(
+            ["create", "--pod", &container.pod, "--name", &container.name]
+                .into_iter()
+                .map(String::from),
+        )
+        .chain(
+            container
+                .requires
+                .iter()
+                .flat_map(|requirement| [String::from("--requires"), requirement.clone()]),
+        )
+        .chain(labels(&container.labels))
+        .chain(container.options.iter().cloned())
+        .chain([container.image.clone()])
+        .chain(container.command.iter().cloned())
+        .collect()
+}
+
+pub(crate) fn start_container_args(name: &str) -> Vec<String> {
+    vec![String::from("start"), name.to_owned()]
+}
+
+pub(crate) fn stop_container_args(name: &str, timeout: Option<u32>) -> Vec<String> {
+    [String::from("stop")]
+        .into_iter()
+        .chain(
+            timeout
+                .into_iter()
+                .flat_map(|timeout| [String::from("--time"), timeout.to_string()]),
+        )
+        .chain([name.to_owned()])
+        .collect()
+}
+
+pub(crate) fn remove_container_args(name: &str, force: bool, volumes: bool) -> Vec<String> {
+    ["rm", "--depend", "--ignore"]
+        .into_iter()
+        .chain(force.then_some("--force"))
+        .chain(volumes.then_some("--volumes"))
+        .chain([name])
+        .map(String::from)
+        .collect()
+}
+
+pub(crate) fn wait_container_args(name: &str) -> Vec<String> {
+    vec![String::from("wait"), name.to_owned()]
+}
+
+pub(crate) fn create_resource_args(resource: &ResourceSpec) -> Vec<String> {
+    [resource.kind.as_str(), "create"]
+        .into_iter()
+        .map(String::from)
+        .chain(labels(&resource.labels))
+        .chain(resource.options.iter().cloned())
+        .chain([resource.name.clone()])
+        .chain(resource.source.clone())
+        .collect()
+}
+
+pub(crate) fn remove_resource_args(kind: ResourceKind, name: &str) -> Vec<String> {
+    [kind.as_str(), "rm", name]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+pub(crate) fn pull_image_args(image: &str, policy: PullPolicy, quiet: bool) -> Vec<String> {
+    ["pull", "--policy", policy.as_str()]
+        .into_iter()
+        .chain(quiet.then_some("--quiet"))
+        .chain([image])
+        .map(String::from)
+        .collect()
+}
+
+pub(crate) fn push_image_args(image: &str) -> Vec<String> {
+    vec![String::from("push"), image.to_owned()]
+}
+
+pub(crate) fn remove_image_args(image: &str) -> Vec<String> {
+    ["image", "rm", image]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+pub(crate) struct Cli {
+    project_directory: PathBuf,
+    api: Option<Client>,
+}
+
+impl Cli {
+    pub(crate) fn new(config: &Config) -> Result<Self> {
+        Ok(Self {
+            project_directory: config.project_directory.clone(),
+            api: if config.query_socket {
+                Some(Client::from_env()?)
+            } else {
+                None
+            },
+        })
+    }
+
+    fn command<I, S>(&self, args: I) -> Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut command = Command::new("podman");
+        command.current_dir(&self.project_directory).args(args);
+
+        command
+    }
+
+    async fn execute(&self, args: Vec<String>) -> Result<()> {
+        self.run(args.into_iter().map(OsString::from).collect())
+            .await
+            .map(|_| ())
+    }
+
+    async fn list<T>(&self, kind: &str, path: &str, filters: Vec<String>) -> Result<Vec<T>>
+    where
+        T: DeserializeOwned,
+    {
+        if let Some(api) = &self.api {
+            api.get(&format!("{path}?filters={}", api::filters(filters)?))
+                .await
+        } else {
+            let output = self
+                .run(
+                    [kind, "ls", "--format", "json"]
+                        .into_iter()
+                        .map(OsString::from)
+                        .chain(
+                            filters
+                                .into_iter()
+                                .flat_map(|filter| ["--filter".into(), filter.into()]),
+                        )
+                        .collect(),
+                )
+                .await?;
+
+            Ok(serde_json::from_str(&output)?)
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ContainerEngine for Cli {
+    async fn version(&self) -> Result<semver::Version> {
+        Ok(if let Some(api) = &self.api {
+            api.get::<VersionComponent>("/version")
+                .await
+                .with_context(|| anyhow!("Podman version not recognised"))?
+                .version
+        } else {
+            let output = self
+                .run(vec!["version".into(), "--format".into(), "json".into()])
+                .await?;
+
+            serde_json::from_str::<Version>(&output)
+                .with_context(|| anyhow!("Podman version not recognised"))?
+                .client
+                .version
+        })
+    }
+
+    async fn run(&self, args: Vec<OsString>) -> Result<String> {
+        let mut command = self.command(args);
+
+        let output = command.output().await.with_context(|| {
+            anyhow!(
+                "`{} {}` cannot be executed",
+                command.as_std().get_program().to_string_lossy(),
+                shell_words::join(
+                    command
+                        .as_std()
+                        .get_args()
+                        .map(|arg| arg.to_string_lossy().to_string())
+                )
+            )
+        })?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        } else {
+            Err(
+                anyhow!("{}", String::from_utf8_lossy(&output.stderr)).context(anyhow!(
+                    "`{} {}` returned an error",
+                    command.as_std().get_program().to_string_lossy(),
+                    shell_words::join(
+                        command
+                            .as_std()
+                            .get_args()
+                            .map(|arg| arg.to_string_lossy().to_string())
+                    )
+                )),
+            )
+        }
+    }
+
+    fn watch(&self, args: Vec<OsString>) -> Result<Pin<Box<dyn Stream<Item = Result<String>>>>> {
+        let child = self
+            .command(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = BufReader::new(child.stdout.unwrap()).lines();
+        let stderr = BufReader::new(child.stderr.unwrap()).lines();
+
+        Ok(select(LinesStream::new(stdout), LinesStream::new(stderr))
+            .map_err(Error::from)
+            .boxed())
+    }
+
+    async fn attach(&self, args: Vec<OsString>) -> Result<()> {
+        let status = self.command(args).spawn()?.wait().await?;
+
+        if !status.success() {
+            process::exit(status.code().unwrap_or(1));
+        }
+
+        Ok(())
+    }
+
+    async fn exists(&self, kind: &str, name: &str) -> Result<bool> {
+        if let Some(api) = &self.api {
+            api.exists(&format!("/{kind}s/{}/exists", api::path(name)))
+                .await
+        } else {
+            Ok(self
+                .run(vec![kind.into(), "exists".into(), name.into()])
+                .await
+                .is_ok())
+        }
+    }
+
+    async fn containers(&self, filters: Vec<String>) -> Result<Vec<Container>> {
+        if let Some(api) = &self.api {
+            api.get(&format!(
+                "/containers/json?all=true&filters={}",
+                api::filters(filters)?
+            ))
+            .await
+        } else {
+            let output = self
+                .run(
+                    ["ps", "--all", "--format", "json"]
+                        .into_iter()
+                        .map(OsString::from)
+                        .chain(
+                            filters
+                                .into_iter()
+                                .flat_map(|filter| ["--filter".into(), filter.into()]),
+                        )
+                        .collect(),
+                )
+                .await?;
+
+            Ok(serde_json::from_str(&output)?)
+        }
+    }
+
+    async fn networks(&self, filters: Vec<String>) -> Result<Vec<Network>> {
+        self.list("network", "/networks/json", filters).await
+    }
+
+    async fn volumes(&self, filters: Vec<String>) -> Result<Vec<Volume>> {
+        self.list("volume", "/volumes/json", filters).await
+    }
+
+    async fn secrets(&self) -> Result<Vec<Secret>> {
+        self.list("secret", "/secrets/json", Vec::new()).await
+    }
+
+    async fn inspect_container(&self, name: &str) -> Result<ContainerState> {
+        if let Some(api) = &self.api {
+            api.get::<ContainerInspect>(&format!("/containers/{}/json", api::path(name)))
+                .await
+                .map(|container| container.state)
+        } else {
+            let output = self
+                .run(vec![
+                    "container".into(),
+                    "inspect".into(),
+                    "--format".into(),
+                    "json".into(),
+                    name.into(),
+                ])
+                .await?;
+
+            serde_json::from_str::<Vec<ContainerInspect>>(&output)?
+                .pop()
+                .map(|container| container.state)
+                .ok_or_else(|| anyhow!("No such container: \"{name}\""))
+        }
+    }
+
+    async fn image_id(&self, image: &str) -> Result<Option<String>> {
+        if !self.exists("image", image).await? {
+            return Ok(None);
+        }
+
+        if let Some(api) = &self.api {
+            api.get::<ImageInspect>(&format!("/images/{}/json", api::path(image)))
+                .await
+                .map(|image| Some(image.id))
+        } else {
+            let output = self
+                .run(vec![
+                    "image".into(),
+                    "inspect".into(),
+                    "--format".into(),
+                    "json".into(),
+                    image.into(),
+                ])
+                .await?;
+
+            Ok(serde_json::from_str::<Vec<ImageInspect>>(&output)?
+                .pop()
+                .map(|image| image.id))
+        }
+    }
+
+    async fn create_pod(&self, pod: &PodSpec) -> Result<()> {
+        self.execute(create_pod_args(pod)).await
+    }
+
+    async fn remove_pod(&self, name: &str) -> Result<()> {
+        self.execute(remove_pod_args(name)).await
+    }
+
+    async fn create_container(&self, container: &ContainerSpec) -> Result<()> {
+        self.execute(create_container_args(container)).await
+    }
+
+    async fn start_container(&self, name: &str) -> Result<()> {
+        self.execute(start_container_args(name)).await
+    }
+
+    async fn stop_container(&self, name: &str, timeout: Option<u32>) -> Result<()> {
+        self.execute(stop_container_args(name, timeout)).await
+    }
+
+    async fn remove_container(&self, name: &str, force: bool, volumes: bool) -> Result<()> {
+        self.execute(remove_container_args(name, force, volumes))
+            .await
+    }
+
+    async fn wait_container(&self, name: &str) -> Result<i32> {
+        let output = self
+            .run(
+                wait_container_args(name)
+                    .into_iter()
+                    .map(OsString::from)
+                    .collect(),
+            )
+            .await?;
+
+        output
+            .trim()
+            .parse()
+            .with_context(|| anyhow!("Exit code of container \"{name}\" not recognised"))
+    }
+
+    async fn create_resource(&self, resource: &ResourceSpec) -> Result<()> {
+        self.execute(create_resource_args(resource)).await
+    }
+
+    async fn remove_resource(&self, kind: ResourceKind, name: &str) -> Result<()> {
+        self.execute(remove_resource_args(kind, name)).await
+    }
+
+    async fn pull_image(&self, image: &str, policy: PullPolicy, quiet: bool) -> Result<()> {
+        self.execute(pull_image_args(image, policy, quiet)).await
+    }
+
+    async fn push_image(&self, image: &str) -> Result<()> {
+        self.execute(push_image_args(image)).await
+    }
+
+    async fn remove_image(&self, image: &str) -> Result<()> {
+        self.execute(remove_image_args(image)).await
+    }
+}
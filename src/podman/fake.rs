@@ -0,0 +1,473 @@
+#![cfg(test)]
+
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    ffi::OsString,
+    path::Path,
+    pin::Pin,
+    rc::Rc,
+};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use futures::{stream, Stream, StreamExt};
+use indexmap::IndexMap;
+use path_absolutize::Absolutize;
+use serde_json::json;
+
+use super::{
+    types::{Container, ContainerState, Network, Secret, Volume},
+    ContainerEngine, ContainerSpec, PodSpec, Podman, PullPolicy, ResourceKind, ResourceSpec,
+};
+use crate::{
+    compose::{self, types::Compose},
+    config::Config,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Operation {
+    CreatePod,
+    RemovePod,
+    Create,
+    Start,
+    Stop,
+    Remove { volumes: bool },
+    Wait,
+    CreateResource(ResourceKind),
+    RemoveResource(ResourceKind),
+    Pull(PullPolicy),
+    Push,
+    RemoveImage,
+    Run,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Call {
+    pub(crate) operation: Operation,
+    pub(crate) target: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct FakeContainer {
+    pub(crate) pod: String,
+    pub(crate) image: String,
+    pub(crate) labels: IndexMap<String, String>,
+    pub(crate) state: String,
+    pub(crate) exit_code: i32,
+    /// Health status of containers created with a healthcheck
+    pub(crate) health: Option<String>,
+}
+
+#[derive(Default, Debug)]
+pub(crate) struct State {
+    pub(crate) calls: Vec<Call>,
+    pub(crate) pods: IndexMap<String, IndexMap<String, String>>,
+    pub(crate) containers: IndexMap<String, FakeContainer>,
+    pub(crate) networks: IndexMap<String, IndexMap<String, String>>,
+    pub(crate) volumes: IndexMap<String, IndexMap<String, String>>,
+    pub(crate) secrets: IndexMap<String, IndexMap<String, String>>,
+    /// Local images and their IDs
+    pub(crate) images: IndexMap<String, String>,
+}
+
+/// In-memory container engine that records calls and simulates their effects
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Fake {
+    state: Rc<RefCell<State>>,
+}
+
+fn labels(labels: &[String]) -> IndexMap<String, String> {
+    labels
+        .iter()
+        .map(|label| {
+            let (key, value) = label.split_once('=').unwrap_or((label, ""));
+
+            (key.to_owned(), value.to_owned())
+        })
+        .collect()
+}
+
+fn matches(labels: &IndexMap<String, String>, filter: &str) -> bool {
+    match filter.split_once('=') {
+        Some((key, value)) => labels.get(key).is_some_and(|label| label == value),
+        None => labels.contains_key(filter),
+    }
+}
+
+impl Fake {
+    pub(crate) fn state(&self) -> Ref<'_, State> {
+        self.state.borrow()
+    }
+
+    pub(crate) fn state_mut(&self) -> RefMut<'_, State> {
+        self.state.borrow_mut()
+    }
+
+    /// Targets of the calls made with the given operation, in order
+    pub(crate) fn targets(&self, operation: Operation) -> Vec<String> {
+        self.state
+            .borrow()
+            .calls
+            .iter()
+            .filter(|call| call.operation == operation)
+            .map(|call| call.target.clone())
+            .collect()
+    }
+
+    pub(crate) fn clear_calls(&self) {
+        self.state.borrow_mut().calls.clear();
+    }
+
+    fn record(&self, operation: Operation, target: &str) -> RefMut<'_, State> {
+        let mut state = self.state.borrow_mut();
+        state.calls.push(Call {
+            operation,
+            target: target.to_owned(),
+        });
+
+        state
+    }
+
+    fn resources(
+        state: &mut State,
+        kind: ResourceKind,
+    ) -> &mut IndexMap<String, IndexMap<String, String>> {
+        match kind {
+            ResourceKind::Network => &mut state.networks,
+            ResourceKind::Volume => &mut state.volumes,
+            ResourceKind::Secret => &mut state.secrets,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl ContainerEngine for Fake {
+    async fn version(&self) -> Result<semver::Version> {
+        Ok(semver::Version::new(4, 9, 0))
+    }
+
+    async fn run(&self, args: Vec<OsString>) -> Result<String> {
+        self.record(
+            Operation::Run,
+            &shell_words::join(args.iter().map(|arg| arg.to_string_lossy())),
+        );
+
+        Ok(String::new())
+    }
+
+    fn watch(&self, args: Vec<OsString>) -> Result<Pin<Box<dyn Stream<Item = Result<String>>>>> {
+        self.record(
+            Operation::Run,
+            &shell_words::join(args.iter().map(|arg| arg.to_string_lossy())),
+        );
+
+        Ok(stream::empty().boxed())
+    }
+
+    async fn attach(&self, args: Vec<OsString>) -> Result<()> {
+        self.run(args).await.map(|_| ())
+    }
+
+    async fn exists(&self, kind: &str, name: &str) -> Result<bool> {
+        let state = self.state.borrow();
+
+        Ok(match kind {
+            "pod" => state.pods.contains_key(name),
+            "container" => state.containers.contains_key(name),
+            "network" => state.networks.contains_key(name),
+            "volume" => state.volumes.contains_key(name),
+            "image" => state.images.contains_key(name),
+            _ => bail!("Unknown resource kind: {kind}"),
+        })
+    }
+
+    async fn containers(&self, filters: Vec<String>) -> Result<Vec<Container>> {
+        let state = self.state.borrow();
+        let mut containers = Vec::new();
+
+        for (index, (name, container)) in state.containers.iter().enumerate() {
+            let mut matched = true;
+
+            for filter in &filters {
+                matched &= match filter.split_once('=') {
+                    Some(("pod", pod)) => container.pod == pod,
+                    Some(("label", label)) => matches(&container.labels, label),
+                    Some(("status", status)) => container.state == status,
+                    Some(("name", container_name)) => name == container_name,
+                    Some(("ancestor", image)) => container.image == image,
+                    _ => bail!("Unsupported filter: {filter}"),
+                };
+            }
+
+            if matched {
+                containers.push(serde_json::from_value(json!({
+                    "Id": format!("{index:064x}"),
+                    "Labels": container.labels,
+                    "Names": [name],
+                    "State": container.state,
+                }))?);
+            }
+        }
+
+        Ok(containers)
+    }
+
+    async fn networks(&self, filters: Vec<String>) -> Result<Vec<Network>> {
+        filter_resources(&self.state.borrow().networks, &filters)?
+            .into_iter()
+            .map(|name| Ok(serde_json::from_value(json!({ "name": name }))?))
+            .collect()
+    }
+
+    async fn volumes(&self, filters: Vec<String>) -> Result<Vec<Volume>> {
+        filter_resources(&self.state.borrow().volumes, &filters)?
+            .into_iter()
+            .map(|name| Ok(serde_json::from_value(json!({ "Name": name }))?))
+            .collect()
+    }
+
+    async fn secrets(&self) -> Result<Vec<Secret>> {
+        self.state
+            .borrow()
+            .secrets
+            .iter()
+            .map(|(name, labels)| {
+                Ok(serde_json::from_value(
+                    json!({ "Spec": { "Name": name, "Labels": labels } }),
+                )?)
+            })
+            .collect()
+    }
+
+    async fn inspect_container(&self, name: &str) -> Result<ContainerState> {
+        let state = self.state.borrow();
+        let container = state
+            .containers
+            .get(name)
+            .ok_or_else(|| anyhow!("no such container {name}"))?;
+
+        Ok(serde_json::from_value(json!({
+            "Status": container.state,
+            "ExitCode": container.exit_code,
+            "Health": container.health.as_ref().map(|health| json!({ "Status": health })),
+        }))?)
+    }
+
+    async fn image_id(&self, image: &str) -> Result<Option<String>> {
+        Ok(self.state.borrow().images.get(image).cloned())
+    }
+
+    async fn create_pod(&self, pod: &PodSpec) -> Result<()> {
+        self.record(Operation::CreatePod, &pod.name)
+            .pods
+            .insert(pod.name.clone(), labels(&pod.labels));
+
+        Ok(())
+    }
+
+    async fn remove_pod(&self, name: &str) -> Result<()> {
+        let mut state = self.record(Operation::RemovePod, name);
+
+        if state
+            .containers
+            .values()
+            .any(|container| container.pod == name)
+        {
+            bail!("pod {name} has containers that are not ready to be removed");
+        }
+
+        state.pods.shift_remove(name);
+
+        Ok(())
+    }
+
+    async fn create_container(&self, container: &ContainerSpec) -> Result<()> {
+        let mut state = self.record(Operation::Create, &container.name);
+
+        if state.containers.contains_key(&container.name) {
+            bail!(
+                "the container name \"{}\" is already in use",
+                container.name
+            );
+        }
+
+        state.containers.insert(
+            container.name.clone(),
+            FakeContainer {
+                pod: container.pod.clone(),
+                image: container.image.clone(),
+                labels: labels(&container.labels),
+                state: String::from("created"),
+                exit_code: 0,
+                health: container
+                    .options
+                    .iter()
+                    .any(|option| option == "--health-cmd")
+                    .then(|| String::from("starting")),
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn start_container(&self, name: &str) -> Result<()> {
+        self.record(Operation::Start, name)
+            .containers
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("no container with name or ID \"{name}\" found"))?
+            .state = String::from("running");
+
+        Ok(())
+    }
+
+    async fn stop_container(&self, name: &str, _timeout: Option<u32>) -> Result<()> {
+        self.record(Operation::Stop, name)
+            .containers
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("no container with name or ID \"{name}\" found"))?
+            .state = String::from("exited");
+
+        Ok(())
+    }
+
+    async fn remove_container(&self, name: &str, _force: bool, volumes: bool) -> Result<()> {
+        self.record(Operation::Remove { volumes }, name)
+            .containers
+            .shift_remove(name);
+
+        Ok(())
+    }
+
+    async fn wait_container(&self, name: &str) -> Result<i32> {
+        let mut state = self.record(Operation::Wait, name);
+        let container = state
+            .containers
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("no container with name or ID \"{name}\" found"))?;
+        container.state = String::from("exited");
+
+        Ok(container.exit_code)
+    }
+
+    async fn create_resource(&self, resource: &ResourceSpec) -> Result<()> {
+        let mut state = self.record(Operation::CreateResource(resource.kind), &resource.name);
+        let resources = Self::resources(&mut state, resource.kind);
+
+        if resources.contains_key(&resource.name) {
+            bail!(
+                "{} {} already exists",
+                resource.kind.as_str(),
+                resource.name
+            );
+        }
+
+        resources.insert(resource.name.clone(), labels(&resource.labels));
+
+        Ok(())
+    }
+
+    async fn remove_resource(&self, kind: ResourceKind, name: &str) -> Result<()> {
+        let mut state = self.record(Operation::RemoveResource(kind), name);
+
+        if Self::resources(&mut state, kind)
+            .shift_remove(name)
+            .is_none()
+        {
+            bail!("no such {}: {name}", kind.as_str());
+        }
+
+        Ok(())
+    }
+
+    async fn pull_image(&self, image: &str, policy: PullPolicy, _quiet: bool) -> Result<()> {
+        let mut state = self.record(Operation::Pull(policy), image);
+        let id = format!("sha256:{:064x}", state.calls.len());
+        state.images.insert(image.to_owned(), id);
+
+        Ok(())
+    }
+
+    async fn push_image(&self, image: &str) -> Result<()> {
+        if !self
+            .record(Operation::Push, image)
+            .images
+            .contains_key(image)
+        {
+            bail!("{image}: image not known");
+        }
+
+        Ok(())
+    }
+
+    async fn remove_image(&self, image: &str) -> Result<()> {
+        let mut state = self.record(Operation::RemoveImage, image);
+
+        if state
+            .containers
+            .values()
+            .any(|container| container.image == image)
+        {
+            bail!("image {image} is in use by a container");
+        }
+
+        state.images.shift_remove(image);
+
+        Ok(())
+    }
+}
+
+fn filter_resources(
+    resources: &IndexMap<String, IndexMap<String, String>>,
+    filters: &[String],
+) -> Result<Vec<String>> {
+    let filters = filters
+        .iter()
+        .map(|filter| {
+            filter
+                .strip_prefix("label=")
+                .ok_or_else(|| anyhow!("Unsupported filter: {filter}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(resources
+        .iter()
+        .filter(|(_, labels)| filters.iter().all(|filter| matches(labels, filter)))
+        .map(|(name, _)| name.clone())
+        .collect())
+}
+
+/// Load a project from `tests/fixtures`
+pub(crate) fn project(path: &str) -> (Config, Compose) {
+    let directory = Path::new(path).parent().unwrap().absolutize().unwrap();
+    let config = Config {
+        files: vec![directory.join(Path::new(path).file_name().unwrap())],
+        project_directory: directory.to_path_buf(),
+        env_file: directory.join(".env"),
+        ..Config::default()
+    };
+    let file = compose::parse(&config, false, &[]).unwrap();
+
+    (config, file)
+}
+
+/// Load a project from `tests/fixtures` along with a fake engine to run it on
+pub(crate) fn setup(path: &str) -> (Config, Compose, Fake, Podman) {
+    let (config, file) = project(path);
+    let engine = Fake::default();
+    let podman = Podman::with_engine(engine.clone(), false);
+
+    (config, file, engine, podman)
+}
+
+/// Parse command arguments the way the command line would
+pub(crate) fn args<T>(args: &[&str]) -> T
+where
+    T: clap::FromArgMatches + clap::Args,
+{
+    T::from_arg_matches(
+        &T::augment_args(clap::Command::new("haddock"))
+            .try_get_matches_from(["haddock"].iter().chain(args))
+            .unwrap(),
+    )
+    .unwrap()
+}
@@ -12,6 +12,7 @@ pub(crate) struct Container {
     #[serde(with = "prefix_io_podman_compose")]
     pub(crate) labels: Option<ContainerLabels>,
     pub(crate) names: VecDeque<String>,
+    pub(crate) state: String,
 }
 
 #[serde_as]
@@ -21,6 +22,42 @@ pub(crate) struct ContainerLabels {
     pub(crate) service: Option<String>,
     #[serde_as(as = "Option<DisplayFromStr>")]
     pub(crate) container_number: Option<usize>,
+    pub(crate) config_hash: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct ContainerInspect {
+    pub(crate) state: ContainerState,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct ContainerState {
+    pub(crate) status: String,
+    pub(crate) exit_code: i32,
+    #[serde(alias = "Healthcheck")]
+    pub(crate) health: Option<Health>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct Health {
+    pub(crate) status: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct ImageInspect {
+    pub(crate) id: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct Event {
+    pub(crate) name: String,
+    pub(crate) status: String,
+    pub(crate) container_exit_code: Option<i32>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -30,16 +67,24 @@ pub(crate) struct Network {
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
-pub(crate) struct Pod {
+pub(crate) struct Secret {
+    pub(crate) spec: SecretSpec,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct SecretSpec {
+    pub(crate) name: String,
     #[serde(with = "prefix_io_podman_compose")]
-    pub(crate) labels: Option<PodLabels>,
+    pub(crate) labels: Option<SecretLabels>,
 }
 
-#[serde_as]
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "kebab-case")]
-pub(crate) struct PodLabels {
-    pub(crate) config_hash: Option<String>,
+pub(crate) struct SecretLabels {
+    pub(crate) project: Option<String>,
+    pub(crate) config: Option<String>,
+    pub(crate) content_hash: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -54,6 +99,12 @@ pub(crate) struct VersionClient {
     pub(crate) version: semver::Version,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct VersionComponent {
+    pub(crate) version: semver::Version,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub(crate) struct Volume {
@@ -45,11 +45,19 @@ pub(crate) struct Progress {
 
 impl Progress {
     pub(crate) fn new(config: &Config) -> Self {
-        let progress = MultiProgress::with_draw_target(if config.dry_run {
+        Self::with_draw_target(if config.dry_run {
             ProgressDrawTarget::hidden()
         } else {
             ProgressDrawTarget::stderr()
-        });
+        })
+    }
+
+    pub(crate) fn hidden() -> Self {
+        Self::with_draw_target(ProgressDrawTarget::hidden())
+    }
+
+    fn with_draw_target(target: ProgressDrawTarget) -> Self {
+        let progress = MultiProgress::with_draw_target(target);
         let header = progress.add(
             ProgressBar::new(0)
                 .with_finish(ProgressFinish::Abandon)
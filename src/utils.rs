@@ -102,7 +102,7 @@ where
     {
         struct AnyVisitor<T>(PhantomData<T>);
 
-        impl<'de, T> Visitor<'de> for AnyVisitor<T>
+        impl<T> Visitor<'_> for AnyVisitor<T>
         where
             T: From<String>,
         {